@@ -21,10 +21,63 @@ fn main() {
     if cfg!(feature = "with-asan") && !cfg!(feature = "build") {
         panic!("Invalid set of options: with-asan should be used with build");
     }
+    if cfg!(feature = "dynamic-link") && cfg!(feature = "build") {
+        panic!("Invalid set of options: dynamic-link can not be used with build");
+    }
 
     let sdir = download_source_code_via_git_if_needed().expect("download of source code failed");
 
-    let bdirs = cmake_build_src_dir(&sdir, is_msvc);
+    if cfg!(feature = "dynamic-link") {
+        link_shared_library();
+    } else {
+        link_static_libraries(&sdir, &target_os, is_msvc);
+    }
+
+    let mut includes = vec![
+        sdir.join("C").join("include"),
+        sdir.join("vendor").join("fleece").join("API"),
+        sdir.clone(),
+    ];
+
+    let (mut addon_include_dirs, framework_dirs) =
+        cc_system_include_dirs().expect("get system include directories from cc failed");
+    includes.append(&mut addon_include_dirs);
+
+    let out_dir = getenv_unwrap("OUT_DIR");
+    let out_dir = Path::new(&out_dir);
+
+    let mut headers = vec![
+        "c4.h",
+        "fleece/FLSlice.h",
+        "fleece/Fleece.h",
+        "fleece/FLExpert.h",
+    ];
+    if cfg!(feature = "use-couchbase-lite-websocket") {
+        headers.push("c4Private.h");
+        includes.push(sdir.join("C"));
+    }
+
+    run_bindgen_for_c_headers(
+        &target,
+        &includes,
+        &framework_dirs,
+        &headers,
+        &out_dir.join("c4_header.rs"),
+    )
+    .expect("bindgen failed");
+}
+
+fn link_shared_library() {
+    const LIB_DIR_VAR: &str = "COUCHBASE_LITE_CORE_LIB_DIR";
+    println!("cargo:rerun-if-env-changed={LIB_DIR_VAR}");
+    if let Ok(lib_dir) = env::var(LIB_DIR_VAR) {
+        println!("cargo:rustc-link-search=native={lib_dir}");
+    }
+    println!("cargo:rustc-link-lib=dylib=LiteCore");
+}
+
+fn link_static_libraries(sdir: &Path, target_os: &str, is_msvc: bool) {
+    let bdirs = cmake_build_src_dir(sdir, is_msvc);
     println!("build directory: {bdirs:?}\nsource directory {sdir:?}");
 
     if bdirs.is_empty() {
@@ -78,39 +131,6 @@ fn main() {
     } else if is_msvc {
         println!("cargo:rustc-link-lib=ws2_32");
     }
-
-    let mut includes = vec![
-        sdir.join("C").join("include"),
-        sdir.join("vendor").join("fleece").join("API"),
-        sdir.clone(),
-    ];
-
-    let (mut addon_include_dirs, framework_dirs) =
-        cc_system_include_dirs().expect("get system include directories from cc failed");
-    includes.append(&mut addon_include_dirs);
-
-    let out_dir = getenv_unwrap("OUT_DIR");
-    let out_dir = Path::new(&out_dir);
-
-    let mut headers = vec![
-        "c4.h",
-        "fleece/FLSlice.h",
-        "fleece/Fleece.h",
-        "fleece/FLExpert.h",
-    ];
-    if cfg!(feature = "use-couchbase-lite-websocket") {
-        headers.push("c4Private.h");
-        includes.push(sdir.join("C"));
-    }
-
-    run_bindgen_for_c_headers(
-        &target,
-        &includes,
-        &framework_dirs,
-        &headers,
-        &out_dir.join("c4_header.rs"),
-    )
-    .expect("bindgen failed");
 }
 
 fn specify_library_search_dirs_for_std_layout(bdir: &Path) {
@@ -47,6 +47,42 @@ pub unsafe fn FLMutableDict_SetString(d: FLMutableDict, key: FLString, val: FLSt
     FLSlot_SetString(FLMutableDict_Set(d, key), val);
 }
 
+#[inline]
+#[allow(non_snake_case)]
+pub unsafe fn FLMutableDict_SetValue(d: FLMutableDict, key: FLString, val: FLValue) {
+    FLSlot_SetValue(FLMutableDict_Set(d, key), val);
+}
+
+#[inline]
+#[allow(non_snake_case)]
+pub unsafe fn FLMutableArray_Release(a: FLMutableArray) {
+    FLValue_Release(a as *const _FLValue);
+}
+
+#[inline]
+#[allow(non_snake_case)]
+pub unsafe fn FLMutableArray_AppendBool(a: FLMutableArray, val: bool) {
+    FLSlot_SetBool(FLMutableArray_Append(a), val);
+}
+
+#[inline]
+#[allow(non_snake_case)]
+pub unsafe fn FLMutableArray_AppendInt(a: FLMutableArray, val: i64) {
+    FLSlot_SetInt(FLMutableArray_Append(a), val);
+}
+
+#[inline]
+#[allow(non_snake_case)]
+pub unsafe fn FLMutableArray_AppendString(a: FLMutableArray, val: FLString) {
+    FLSlot_SetString(FLMutableArray_Append(a), val);
+}
+
+#[inline]
+#[allow(non_snake_case)]
+pub unsafe fn FLMutableArray_AppendValue(a: FLMutableArray, val: FLValue) {
+    FLSlot_SetValue(FLMutableArray_Append(a), val);
+}
+
 #[inline]
 pub unsafe fn c4query_release(r: *mut C4Query) {
     c4base_release(r as *mut std::os::raw::c_void)
@@ -1,10 +1,15 @@
 //! Code to help deal with C API
 
 use crate::{
-    C4CollectionSpec, C4String, FLHeapSlice, FLSlice, FLSliceResult, FLSliceResult_Release,
-    FLString,
+    c4_now, C4CollectionSpec, C4String, C4Timestamp, FLHeapSlice, FLSlice, FLSliceResult,
+    FLSliceResult_Release, FLString,
+};
+use std::{
+    borrow::Cow,
+    os::raw::c_void,
+    ptr, slice, str,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use std::{borrow::Cow, os::raw::c_void, ptr, slice, str};
 
 impl Default for FLSlice {
     #[inline]
@@ -44,6 +49,24 @@ impl<'a> From<&'a [u8]> for FLSlice {
     }
 }
 
+// `FLSlice` doesn't track lifetime of data, so there is no conversion
+// from owned `String` or `Vec<u8>`: it would point to freed memory.
+// Caller should keep `String`/`Vec<u8>` alive while `FLSlice` in use.
+
+impl<'a> From<&'a String> for FLSlice {
+    #[inline]
+    fn from(s: &'a String) -> Self {
+        s.as_str().into()
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for FLSlice {
+    #[inline]
+    fn from(ba: &'a Vec<u8>) -> Self {
+        ba.as_slice().into()
+    }
+}
+
 impl<'a> From<FLSlice> for &'a [u8] {
     #[inline]
     fn from(s: FLSlice) -> Self {
@@ -148,6 +171,46 @@ pub const kC4DefaultCollectionSpec: C4CollectionSpec = C4CollectionSpec {
     scope: kC4DefaultScopeID,
 };
 
+/// Current time according to couchbase-lite-core,
+/// milliseconds since Unix epoch
+#[inline]
+pub fn c4_timestamp_now() -> C4Timestamp {
+    unsafe { c4_now() }
+}
+
+/// Convert `SystemTime` to `C4Timestamp` (milliseconds since Unix epoch),
+/// values outside of `C4Timestamp` range are saturated
+pub fn system_time_to_c4_timestamp(time: SystemTime) -> C4Timestamp {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => C4Timestamp::try_from(after.as_millis()).unwrap_or(C4Timestamp::MAX),
+        Err(err) => C4Timestamp::try_from(err.duration().as_millis())
+            .map(|ms| -ms)
+            .unwrap_or(C4Timestamp::MIN),
+    }
+}
+
+/// Convert `C4Timestamp` (milliseconds since Unix epoch) to `SystemTime`
+pub fn c4_timestamp_to_system_time(timestamp: C4Timestamp) -> SystemTime {
+    let ms = Duration::from_millis(timestamp.unsigned_abs());
+    if timestamp >= 0 {
+        UNIX_EPOCH + ms
+    } else {
+        UNIX_EPOCH - ms
+    }
+}
+
+#[test]
+fn test_c4_timestamp_conversion() {
+    for ts in [0, 1, -1, 1_700_000_000_123] {
+        assert_eq!(
+            ts,
+            system_time_to_c4_timestamp(c4_timestamp_to_system_time(ts))
+        );
+    }
+    let now = system_time_to_c4_timestamp(SystemTime::now());
+    assert!((c4_timestamp_now() - now).abs() < 60_000);
+}
+
 #[test]
 fn test_null_slice_handling() {
     let ffi_null_slice = FLSlice {
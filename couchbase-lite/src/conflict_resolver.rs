@@ -1,58 +1,158 @@
 use crate::{
-    document::C4DocumentOwner,
+    document::{C4DocumentOwner, Document},
     error::{c4error_init, Error, Result},
     ffi::{
         c4doc_resolveConflict, c4doc_save, c4doc_selectNextLeafRevision, C4DocContentLevel,
-        C4RevisionFlags, FLSlice, FLSlice_Compare,
+        C4ErrorCode, C4ErrorDomain, C4RevisionFlags, FLSlice, FLSliceResult, FLSlice_Compare,
     },
     Database,
 };
 use log::{info, warn};
-use std::{os::raw::c_uint, ptr};
+use std::{os::raw::c_uint, ptr, str};
 
-/// Resolves a replication conflict in a document
-pub fn resolve_conflict(db: &mut Database, doc_id: &str) -> Result<()> {
+/// Outcome of conflict resolution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictResolution {
+    /// Nothing to resolve: document no longer exists or conflict already resolved
+    NoConflict,
+    /// Local revision won, `rev_id` is id of resulting revision
+    LocalWon { rev_id: String },
+    /// Remote revision won, `rev_id` is id of resulting revision
+    RemoteWon { rev_id: String },
+    /// Merged body was saved, `rev_id` is id of resulting revision
+    Merged { rev_id: String },
+    /// Conflict was resolved by deletion of document,
+    /// `rev_id` is id of resulting (deleted) revision
+    Deleted { rev_id: String },
+}
+
+const MAX_RETRY_COUNT: u8 = 10;
+
+/// Resolves a replication conflict in a document,
+/// if saving of resolution keeps conflicting with newer revisions
+/// gives up after several retries and returns conflict error
+pub fn resolve_conflict(db: &mut Database, doc_id: &str) -> Result<ConflictResolution> {
+    resolve_conflict_loop(db, doc_id, |db, doc_id, conflict| {
+        default_resolve_conflict(db, doc_id, &conflict)
+    })
+}
+
+/// Result of custom conflict resolution
+pub enum MergedBody {
+    /// Local revision wins
+    UseLocal,
+    /// Remote revision wins
+    UseRemote,
+    /// Save fleece encoded body as resolving revision
+    Merged(FLSliceResult),
+    /// Resolve conflict by deletion of document
+    Deleted,
+}
+
+/// Resolves a replication conflict in a document with custom resolver,
+/// `resolver` gets local and remote revisions of document and produces resolution
+pub fn resolve_conflict_with<F>(
+    db: &mut Database,
+    doc_id: &str,
+    mut resolver: F,
+) -> Result<ConflictResolution>
+where
+    F: FnMut(&Document, &Document) -> MergedBody,
+{
+    resolve_conflict_loop(db, doc_id, |db, doc_id, conflict| {
+        custom_resolve_conflict(db, doc_id, conflict, &mut resolver)
+    })
+}
+
+fn resolve_conflict_loop<F>(
+    db: &mut Database,
+    doc_id: &str,
+    mut resolve: F,
+) -> Result<ConflictResolution>
+where
+    F: FnMut(&mut Database, &str, C4DocumentOwner) -> Result<ConflictResolution>,
+{
     let mut retry_count = 0_u8;
-    const MAX_RETRY_COUNT: u8 = 10;
     loop {
         let doc = match db.do_internal_get_opt(doc_id, true, C4DocContentLevel::kDocGetAll)? {
             Some(x) => x,
             None => {
                 info!("doc {doc_id} no longer exists, no conflict to resolve");
-                return Ok(());
+                return Ok(ConflictResolution::NoConflict);
             }
         };
 
         let ok = select_next_conflicting_revision(&doc)?;
         if !ok {
             info!("conflict in doc {doc_id} already resolved, nothing to do");
-            return Ok(());
+            return Ok(ConflictResolution::NoConflict);
         }
-        let ok = default_resolve_conflict(db, doc_id, &doc)?;
-        let mut in_conflict = false;
-        if !ok {
-            retry_count += 1;
-            in_conflict = retry_count < MAX_RETRY_COUNT;
-            if in_conflict {
-                warn!(
-                    "conflict resolution of doc '{}' conflicted with newer saved, retry {}",
-                    doc_id, retry_count
-                );
-            }
+        match resolve(db, doc_id, doc) {
+            Err(Error::C4Error(c4err))
+                if c4err.domain == C4ErrorDomain::LiteCoreDomain
+                    && c4err.code == C4ErrorCode::kC4ErrorConflict.0
+                    && retry_count + 1 < MAX_RETRY_COUNT => {}
+            ret => return ret,
         }
+        retry_count += 1;
+        warn!(
+            "conflict resolution of doc '{}' conflicted with newer saved, retry {}",
+            doc_id, retry_count
+        );
+    }
+}
 
-        if !in_conflict {
-            break;
+fn custom_resolve_conflict<F>(
+    db: &mut Database,
+    doc_id: &str,
+    conflict: C4DocumentOwner,
+    resolver: &mut F,
+) -> Result<ConflictResolution>
+where
+    F: FnMut(&Document, &Document) -> MergedBody,
+{
+    let local_doc = match db.do_internal_get_opt(doc_id, true, C4DocContentLevel::kDocGetAll)? {
+        Some(x) => Document::new_internal(x, doc_id),
+        None => {
+            info!("doc {doc_id} no longer exists, no conflict to resolve");
+            return Ok(ConflictResolution::NoConflict);
+        }
+    };
+    let mut remote_doc = Document::new_internal(conflict, doc_id);
+    let merged = resolver(&local_doc, &remote_doc);
+    let conflict = remote_doc.inner.take().ok_or_else(|| {
+        Error::LogicError(format!("Document {doc_id} lost underlying C4Document"))
+    })?;
+    match merged {
+        MergedBody::UseRemote => {
+            let rev_id = do_resolve_conflict(db, &conflict, Resolution::UseRemote, None)?;
+            Ok(ConflictResolution::RemoteWon { rev_id })
+        }
+        MergedBody::UseLocal => {
+            let local_doc = local_doc.inner.as_ref().ok_or_else(|| {
+                Error::LogicError(format!("Document {doc_id} lost underlying C4Document"))
+            })?;
+            let body = local_doc.load_body()?;
+            let rev_id = do_resolve_conflict(db, &conflict, Resolution::UseLocal, Some(body))?;
+            Ok(ConflictResolution::LocalWon { rev_id })
+        }
+        MergedBody::Merged(body) => {
+            let rev_id =
+                do_resolve_conflict(db, &conflict, Resolution::UseLocal, Some(body.as_bytes()))?;
+            Ok(ConflictResolution::Merged { rev_id })
+        }
+        MergedBody::Deleted => {
+            let rev_id = do_resolve_conflict(db, &conflict, Resolution::UseLocal, None)?;
+            Ok(ConflictResolution::Deleted { rev_id })
         }
     }
-    Ok(())
 }
 
 fn default_resolve_conflict(
     db: &mut Database,
     doc_id: &str,
     conflict: &C4DocumentOwner,
-) -> Result<bool> {
+) -> Result<ConflictResolution> {
     let remote_doc = if (conflict.selected_revision().flags & C4RevisionFlags::kRevDeleted)
         != C4RevisionFlags(0)
     {
@@ -80,7 +180,16 @@ fn default_resolve_conflict(
     } else {
         Resolution::UseLocal
     };
-    do_resolve_conflict(db, conflict, resolution, resolved)
+    let merged_body = match resolved {
+        Some(doc) if resolution != Resolution::UseRemote => Some(doc.load_body()?),
+        _ => None,
+    };
+    let rev_id = do_resolve_conflict(db, conflict, resolution, merged_body)?;
+    Ok(match (resolution, resolved) {
+        (Resolution::UseRemote, _) => ConflictResolution::RemoteWon { rev_id },
+        (Resolution::UseLocal, Some(_)) => ConflictResolution::LocalWon { rev_id },
+        (Resolution::UseLocal, None) => ConflictResolution::Deleted { rev_id },
+    })
 }
 
 fn default_conflict_resolver<'b>(
@@ -133,8 +242,8 @@ fn do_resolve_conflict(
     db: &mut Database,
     conflict_doc: &C4DocumentOwner,
     resolution: Resolution,
-    resolved_doc: Option<&C4DocumentOwner>,
-) -> Result<bool> {
+    merged_body: Option<&[u8]>,
+) -> Result<String> {
     let tx = db.transaction()?;
     // Remote Revision always win so that the resolved revision will not conflict with the remote:
     let winner = unsafe { conflict_doc.0.as_ref() }
@@ -148,8 +257,7 @@ fn do_resolve_conflict(
     // under the remote branch which is the winning branch. When useRemote (remote wins)
     // is true, the remote revision will be kept as is and the losing branch will be pruned.
     if resolution != Resolution::UseRemote {
-        if let Some(resolved_doc) = resolved_doc {
-            let body = resolved_doc.load_body()?;
+        if let Some(body) = merged_body {
             merge_body = body.into();
         } else {
             merge_flags = C4RevisionFlags::kRevDeleted;
@@ -173,5 +281,73 @@ fn do_resolve_conflict(
         return Err(c4err.into());
     }
     tx.commit()?;
-    Ok(true)
+    let rev_id = str::from_utf8(conflict_doc.revision_id()).map_err(|_| Error::InvalidUtf8)?;
+    Ok(rev_id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ffi::{c4doc_put, C4DocPutRequest, C4Error},
+        DatabaseFlags,
+    };
+    use std::{collections::HashMap, ptr::NonNull};
+    use tempfile::tempdir;
+
+    fn put_existing_revision(db: &mut Database, doc_id: &str, history: &[&str], remote_db_id: u32) {
+        let body = serde_fleece::to_fl_slice_result(&HashMap::from([("rev", history[0])])).unwrap();
+        let history: Vec<FLSlice> = history.iter().map(|rev_id| (*rev_id).into()).collect();
+        let rq = C4DocPutRequest {
+            body: body.as_fl_slice(),
+            docID: doc_id.into(),
+            revFlags: C4RevisionFlags(0),
+            existingRevision: true,
+            allowConflict: true,
+            history: history.as_ptr(),
+            historyCount: history.len(),
+            save: true,
+            maxRevTreeDepth: 0,
+            remoteDBID: remote_db_id,
+            allocedBody: FLSliceResult::default(),
+            deltaCB: None,
+            deltaCBContext: ptr::null_mut(),
+            deltaSourceRevID: FLSlice::default(),
+        };
+        let c4db = db.inner.0.as_ptr();
+        let tx = db.transaction().unwrap();
+        let mut c4err = c4error_init();
+        let doc = NonNull::new(unsafe { c4doc_put(c4db, &rq, ptr::null_mut(), &mut c4err) })
+            .expect("c4doc_put failed");
+        drop(C4DocumentOwner(doc));
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn test_resolve_conflict_retries_exhausted() {
+        let tmp_dir = tempdir().expect("Can not create tmp directory");
+        let db_path = tmp_dir.path().join("a.cblite2");
+        let mut db = Database::open_with_flags(&db_path, DatabaseFlags::CREATE).unwrap();
+        put_existing_revision(&mut db, "doc", &["1-aaaa"], 0);
+        put_existing_revision(&mut db, "doc", &["2-bbbb", "1-aaaa"], 0);
+        put_existing_revision(&mut db, "doc", &["2-cccc", "1-aaaa"], 1);
+
+        let mut calls = 0_u8;
+        let ret = resolve_conflict_loop(&mut db, "doc", |_, _, _| {
+            calls += 1;
+            Err(Error::C4Error(C4Error {
+                domain: C4ErrorDomain::LiteCoreDomain,
+                code: C4ErrorCode::kC4ErrorConflict.0,
+                internal_info: 0,
+            }))
+        });
+        assert_eq!(MAX_RETRY_COUNT, calls);
+        match ret {
+            Err(Error::C4Error(c4err)) => {
+                assert_eq!(C4ErrorDomain::LiteCoreDomain, c4err.domain);
+                assert_eq!(C4ErrorCode::kC4ErrorConflict.0, c4err.code);
+            }
+            ret => panic!("expect conflict error, got {ret:?}"),
+        }
+    }
 }
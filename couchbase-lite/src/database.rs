@@ -1,16 +1,19 @@
 use crate::{
     doc_enumerator::{DocEnumerator, DocEnumeratorFlags},
-    document::{C4DocumentOwner, Document},
+    document::{C4DocumentOwner, ContentLevel, Document},
     error::{c4error_init, Error, Result},
     ffi::{
-        c4db_createIndex, c4db_getDoc, c4db_getDocumentCount, c4db_getIndexesInfo, c4db_getName,
-        c4db_getSharedFleeceEncoder, c4db_openNamed, c4db_release, C4Database, C4DatabaseConfig2,
-        C4DatabaseFlags, C4DocContentLevel, C4EncryptionAlgorithm, C4EncryptionKey, C4ErrorCode,
-        C4ErrorDomain, C4IndexOptions, C4IndexType,
+        c4coll_getDocumentCount, c4db_copyNamed, c4db_createIndex2, c4db_delete, c4db_exists,
+        c4db_getCollection, c4db_getConfig2, c4db_getDoc, c4db_getDocumentCount,
+        c4db_getFLSharedKeys, c4db_getIndexesInfo, c4db_getName, c4db_getSharedFleeceEncoder,
+        c4db_maintenance, c4db_openNamed, c4db_release, c4doc_getBySequence, C4Collection,
+        C4CollectionSpec, C4Database, C4DatabaseConfig2, C4DatabaseFlags, C4DocContentLevel,
+        C4EncryptionAlgorithm, C4EncryptionKey, C4ErrorCode, C4ErrorDomain, C4IndexOptions,
+        C4IndexType, C4MaintenanceType, C4SequenceNumber,
     },
-    index::{DbIndexesListIterator, IndexInfo, IndexOptions, IndexType},
+    index::{array_index_expression, DbIndexesListIterator, IndexInfo, IndexOptions, IndexType},
     log_reroute::c4log_to_log_init,
-    observer::{DatabaseObserver, ObserverdChangesIter},
+    observer::{DatabaseObserver, ObserverdChangesIter, DEFAULT_OBSERVED_CHANGES_BATCH_SIZE},
     query::Query,
     transaction::Transaction,
     QueryLanguage,
@@ -18,15 +21,18 @@ use crate::{
 use bitflags::bitflags;
 use fallible_streaming_iterator::FallibleStreamingIterator;
 use log::{debug, error, trace};
-use serde_fleece::FlEncoderSession;
+use serde::Serialize;
+use serde_fleece::{FlEncoderSession, SharedKeys};
 use std::{
     collections::HashSet,
     ffi::CString,
+    fs,
     marker::PhantomData,
-    path::Path,
+    path::{Path, PathBuf},
     ptr::{self, NonNull},
     sync::{Arc, Mutex, Once},
 };
+use uuid::Uuid;
 
 /// Database configuration, used during open
 pub struct DatabaseConfig<'a> {
@@ -42,6 +48,11 @@ bitflags! {
         const CREATE = C4DatabaseFlags::kC4DB_Create.0;
         /// Open file read-only
         const READ_ONLY = C4DatabaseFlags::kC4DB_ReadOnly.0;
+        /// Store documents' revisions as version vectors instead of revision trees.
+        /// Once database is upgraded to version vectors it can not be opened
+        /// without this flag and can not be downgraded back to revision trees,
+        /// also it can replicate only with peers that support version vectors.
+        const VERSION_VECTORS = C4DatabaseFlags::kC4DB_VersionVectors.0;
         /// Disable upgrading an older-version database
         const NO_UPGRADE = C4DatabaseFlags::kC4DB_NoUpgrade.0;
         /// Disable database/collection observers, for slightly faster writes
@@ -79,6 +90,8 @@ pub struct Database {
     pub(crate) inner: DbInner,
     pub(crate) db_events: Arc<Mutex<HashSet<usize>>>,
     pub(crate) db_observers: Vec<DatabaseObserver>,
+    /// directory with database files that should be removed on drop
+    transient_dir: Option<PathBuf>,
 }
 
 pub(crate) struct DbInner(pub NonNull<C4Database>);
@@ -98,6 +111,15 @@ impl Drop for Database {
     #[inline]
     fn drop(&mut self) {
         self.db_observers.clear();
+        if let Some(transient_dir) = self.transient_dir.take() {
+            let mut c4err = c4error_init();
+            if !unsafe { c4db_delete(self.inner.0.as_ptr(), &mut c4err) } {
+                error!("Can not delete transient database: {}", Error::from(c4err));
+            }
+            if let Err(err) = fs::remove_dir_all(&transient_dir) {
+                error!("Can not remove {}: {err}", transient_dir.display());
+            }
+        }
     }
 }
 
@@ -115,6 +137,7 @@ impl Database {
                 inner: DbInner(inner),
                 db_events: Arc::new(Mutex::new(HashSet::new())),
                 db_observers: Vec::new(),
+                transient_dir: None,
             })
             .ok_or_else(|| error.into())
     }
@@ -137,6 +160,49 @@ impl Database {
 
         Database::open_named(db_name, cfg)
     }
+    /// Copy database file `from` (path to `.cblite2` directory) into
+    /// `cfg`'s parent directory with name `to_name`, for example
+    /// to seed writable location from pre-built database.
+    /// Replication checkpoints are preserved. Encryption key from `cfg`
+    /// is used for the new database.
+    /// Returns error if the destination database already exists,
+    /// existing database is never overwritten.
+    pub fn copy(from: &Path, to_name: &str, cfg: DatabaseConfig) -> Result<()> {
+        let cfg = cfg.inner?;
+        let from = from.to_str().ok_or(Error::InvalidUtf8)?;
+        if unsafe { c4db_exists(to_name.into(), cfg.parentDirectory) } {
+            return Err(Error::LogicError(format!(
+                "Can not copy database to {to_name}: destination already exists"
+            )));
+        }
+        let mut c4err = c4error_init();
+        if unsafe { c4db_copyNamed(from.into(), to_name.into(), &cfg, &mut c4err) } {
+            Ok(())
+        } else {
+            Err(c4err.into())
+        }
+    }
+    /// Open database in new unique temporary directory,
+    /// all database files are deleted when `Database` is dropped.
+    /// couchbase-lite-core has no pure in-memory storage,
+    /// so this is the closest analog, useful for tests and caches.
+    pub fn open_in_memory(name: &str) -> Result<Self> {
+        let transient_dir = std::env::temp_dir().join(format!("cblite-{name}-{}", Uuid::new_v4()));
+        fs::create_dir_all(&transient_dir).map_err(|err| {
+            Error::LogicError(format!("Can not create {}: {err}", transient_dir.display()))
+        })?;
+        let cfg = DatabaseConfig::new(&transient_dir, DatabaseFlags::CREATE);
+        match Database::open_named(name, cfg) {
+            Ok(mut db) => {
+                db.transient_dir = Some(transient_dir);
+                Ok(db)
+            }
+            Err(err) => {
+                let _ = fs::remove_dir_all(&transient_dir);
+                Err(err)
+            }
+        }
+    }
     /// Begin a new transaction, the transaction defaults to rolling back
     /// when it is dropped. If you want the transaction to commit,
     /// you must call `Transaction::commit`
@@ -144,6 +210,26 @@ impl Database {
     pub fn transaction(&mut self) -> Result<Transaction> {
         Transaction::new(self)
     }
+    /// Import many documents, `None` as document id means generate new id.
+    /// Documents are saved with one encoder session via `Transaction::save_all`,
+    /// every `batch_size` documents transaction is commited, so memory usage
+    /// doesn't grow with number of documents.
+    /// Returns ids of saved documents.
+    pub fn bulk_import<'a, T, I>(&mut self, docs: I, batch_size: usize) -> Result<Vec<String>>
+    where
+        T: Serialize + 'a,
+        I: IntoIterator<Item = (Option<&'a str>, &'a T)>,
+    {
+        let batch_size = batch_size.max(1);
+        let mut docs = docs.into_iter().peekable();
+        let mut ids = Vec::with_capacity(docs.size_hint().0);
+        while docs.peek().is_some() {
+            let mut trans = self.transaction()?;
+            ids.extend(trans.save_all(docs.by_ref().take(batch_size))?);
+            trans.commit()?;
+        }
+        Ok(ids)
+    }
     /// Returns the number of (undeleted) documents in the database
     #[inline]
     pub fn document_count(&self) -> u64 {
@@ -155,6 +241,38 @@ impl Database {
         self.internal_get(doc_id, true)
             .map(|x| Document::new_internal(x, doc_id))
     }
+    /// Return existing document from database, loading only
+    /// the part of it specified by `level`
+    #[inline]
+    pub fn get_existing_with_level(&self, doc_id: &str, level: ContentLevel) -> Result<Document> {
+        self.do_internal_get(doc_id, true, level.into())
+            .map(|x| Document::new_internal(x, doc_id))
+    }
+    /// Return document with sequence number `seq`, if there is no such
+    /// sequence (for example document was updated after that) returns `None`
+    pub fn get_doc_by_sequence(&self, seq: C4SequenceNumber) -> Result<Option<Document>> {
+        let mut c4err = c4error_init();
+        let c4doc = unsafe { c4doc_getBySequence(self.inner.0.as_ptr(), seq, &mut c4err) };
+        match NonNull::new(c4doc) {
+            Some(c4doc) => {
+                let c4doc = C4DocumentOwner(c4doc);
+                let doc_id = c4doc.id()?.to_string();
+                Ok(Some(Document::new_internal(c4doc, doc_id)))
+            }
+            None if c4err.domain == C4ErrorDomain::LiteCoreDomain
+                && c4err.code == C4ErrorCode::kC4ErrorNotFound.0 =>
+            {
+                Ok(None)
+            }
+            None => Err(c4err.into()),
+        }
+    }
+    /// Check if document with `doc_id` exists and not deleted,
+    /// only metadata of document is loaded, not body
+    pub fn document_exists(&self, doc_id: &str) -> Result<bool> {
+        let doc = self.do_internal_get_opt(doc_id, true, C4DocContentLevel::kDocGetMetadata)?;
+        Ok(doc.map(|x| x.exists() && !x.is_deleted()).unwrap_or(false))
+    }
     /// Compiles a query from an expression given as JSON.
     /// The expression is a predicate that describes which documents should be returned.
     /// A separate, optional sort expression describes the ordering of the results.
@@ -167,6 +285,37 @@ impl Database {
     pub fn n1ql_query(&self, query: &str) -> Result<Query> {
         Query::new(self, QueryLanguage::kC4N1QLQuery, query)
     }
+    /// Returns the number of documents that `enumerate_all_docs` with `flags`
+    /// would return. For not deleted documents (conflicted or not)
+    /// counter maintained by database is used, in other cases documents are
+    /// enumerated without loading bodies.
+    pub fn document_count_with_flags(&self, mut flags: DocEnumeratorFlags) -> Result<u64> {
+        if !flags.contains(DocEnumeratorFlags::INCLUDE_DELETED)
+            && flags.contains(DocEnumeratorFlags::INCLUDE_NON_CONFLICTED)
+        {
+            return Ok(self.document_count());
+        }
+        flags.remove(DocEnumeratorFlags::INCLUDE_BODIES | DocEnumeratorFlags::DESCENDING);
+        let count = self.enumerate_all_docs(flags)?.count()?;
+        Ok(count as u64)
+    }
+    /// Returns the number of documents in collection `spec`,
+    /// with `include_deleted` deleted documents are counted too
+    /// (they are enumerated without loading bodies)
+    pub fn collection_document_count(
+        &self,
+        spec: C4CollectionSpec,
+        include_deleted: bool,
+    ) -> Result<u64> {
+        let collection = self.collection(spec)?;
+        if !include_deleted {
+            return Ok(unsafe { c4coll_getDocumentCount(collection.as_ptr()) });
+        }
+        let flags =
+            DocEnumeratorFlags::INCLUDE_DELETED | DocEnumeratorFlags::INCLUDE_NON_CONFLICTED;
+        let count = DocEnumerator::enumerate_collection_docs(self, collection, flags)?.count()?;
+        Ok(count as u64)
+    }
     /// Creates an enumerator ordered by docID.
     #[inline]
     pub fn enumerate_all_docs(&self, flags: DocEnumeratorFlags) -> Result<DocEnumerator> {
@@ -210,9 +359,18 @@ impl Database {
     /// Get observed changes for this database
     #[inline]
     pub fn observed_changes(&mut self) -> ObserverdChangesIter {
+        self.observed_changes_with_batch_size(DEFAULT_OBSERVED_CHANGES_BATCH_SIZE)
+    }
+
+    /// Get observed changes for this database, reading up to `batch_size`
+    /// changes per call into LiteCore. Observer's callback is fired again only
+    /// after all changes were read, so iterate till the end: changes already
+    /// read into batch but not returned are lost if iterator is dropped earlier
+    pub fn observed_changes_with_batch_size(&mut self, batch_size: u32) -> ObserverdChangesIter {
         ObserverdChangesIter {
             db: self,
             obs_it: None,
+            batch_size: batch_size.max(1),
         }
     }
 
@@ -224,6 +382,14 @@ impl Database {
         crate::replicator::init_builtin_socket_impl();
     }
 
+    /// Intialize socket implementation for replication
+    /// (blocking websocket served by own thread, no async runtime required)
+    #[cfg(feature = "use-std-websocket")]
+    #[inline]
+    pub fn init_std_socket_impl() {
+        crate::replicator::init_std_socket_impl();
+    }
+
     /// Intialize socket implementation for replication
     /// (builtin couchbase-lite websocket library)
     #[cfg(feature = "use-tokio-websocket")]
@@ -232,6 +398,26 @@ impl Database {
         crate::replicator::init_tokio_socket_impl(handle);
     }
 
+    /// Intialize socket implementation for replication
+    /// (tokio websocket with TLS via rustls), `tls_config` allows
+    /// to use custom root certificates store
+    #[cfg(all(feature = "use-tokio-websocket", feature = "use-rustls"))]
+    #[inline]
+    pub fn init_socket_impl_with_rustls(
+        handle: tokio::runtime::Handle,
+        tls_config: std::sync::Arc<rustls::ClientConfig>,
+    ) {
+        crate::replicator::init_tokio_rustls_socket_impl(handle, tls_config);
+    }
+
+    /// Register custom transport for replication instead of
+    /// builtin socket implementations. Like `init_socket_impl` only
+    /// the first registration per process takes effect
+    #[inline]
+    pub fn register_socket_factory<F: crate::SocketFactoryImpl>(factory: F) {
+        crate::replicator::init_custom_socket_impl(factory);
+    }
+
     /// Get shared "fleece" encoder, `&mut self` to make possible
     /// exists only one session
     pub fn shared_encoder_session(&mut self) -> Result<FlEncoderSession> {
@@ -263,6 +449,7 @@ impl Database {
     /// replaced unless it has the exact same expressions.
     /// Note: If some documents are missing the values to be indexed,
     /// those documents will just be omitted from the index. It's not an error.
+    #[inline]
     pub fn create_index(
         &mut self,
         index_name: &str,
@@ -270,13 +457,48 @@ impl Database {
         index_type: IndexType,
         index_options: Option<IndexOptions>,
     ) -> Result<()> {
-        use IndexType::*;
-        let index_type = match index_type {
-            ValueIndex => C4IndexType::kC4ValueIndex,
-            FullTextIndex => C4IndexType::kC4FullTextIndex,
-            ArrayIndex => C4IndexType::kC4ArrayIndex,
-            PredictiveIndex => C4IndexType::kC4PredictiveIndex,
+        self.create_index_with_language(
+            index_name,
+            expression_json,
+            QueryLanguage::kC4JSONQuery,
+            index_type,
+            index_options,
+        )
+    }
+
+    /// The same as `create_index`, but `expression` can be written in any
+    /// supported query language, for example in N1QL: "s, i"
+    /// instead of JSON `[[".s"], [".i"]]`
+    pub fn create_index_with_language(
+        &mut self,
+        index_name: &str,
+        expression: &str,
+        expression_language: QueryLanguage,
+        index_type: IndexType,
+        index_options: Option<IndexOptions>,
+    ) -> Result<()> {
+        if index_type == IndexType::PredictiveIndex {
+            return Err(Error::LogicError(
+                "IndexType::PredictiveIndex is not supported: no way to register prediction model"
+                    .into(),
+            ));
+        }
+        let unnest_path = index_options.as_ref().and_then(|x| x.unnest_path);
+        let array_expression;
+        let expression = match (index_type, unnest_path) {
+            (IndexType::ArrayIndex, Some(unnest_path)) => {
+                array_expression =
+                    array_index_expression(unnest_path, expression, expression_language)?;
+                array_expression.as_str()
+            }
+            (_, Some(_)) => {
+                return Err(Error::LogicError(
+                    "IndexOptions::unnest_path can be used only with IndexType::ArrayIndex".into(),
+                ))
+            }
+            (_, None) => expression,
         };
+        let index_type = C4IndexType::from(index_type);
         let mut c4err = c4error_init();
         let result = if let Some(index_options) = index_options {
             let language = CString::new(index_options.language)?;
@@ -297,13 +519,14 @@ impl Database {
                 language: language.as_ptr(),
                 disableStemming: index_options.disable_stemming,
                 ignoreDiacritics: index_options.ignore_diacritics,
-                stopWords: stop_words.map_or(ptr::null(), |x| x.as_ptr()),
+                stopWords: stop_words.as_ref().map_or(ptr::null(), |x| x.as_ptr()),
             };
             unsafe {
-                c4db_createIndex(
+                c4db_createIndex2(
                     self.inner.0.as_ptr(),
                     index_name.into(),
-                    expression_json.into(),
+                    expression.into(),
+                    expression_language,
                     index_type,
                     &opts,
                     &mut c4err,
@@ -311,10 +534,11 @@ impl Database {
             }
         } else {
             unsafe {
-                c4db_createIndex(
+                c4db_createIndex2(
                     self.inner.0.as_ptr(),
                     index_name.into(),
-                    expression_json.into(),
+                    expression.into(),
+                    expression_language,
                     index_type,
                     ptr::null(),
                     &mut c4err,
@@ -328,6 +552,51 @@ impl Database {
         }
     }
 
+    /// Rebuild all indexes of database, for example after bulk import.
+    /// couchbase-lite-core has no maintenance for single index,
+    /// so all indexes are rebuilt.
+    #[inline]
+    pub fn reindex(&mut self) -> Result<()> {
+        self.maintenance(C4MaintenanceType::kC4Reindex)
+    }
+
+    /// Check database for corruption, problem found by couchbase-lite-core
+    /// is reported as `Error::C4Error` with detailed message
+    #[inline]
+    pub fn integrity_check(&self) -> Result<()> {
+        self.maintenance(C4MaintenanceType::kC4IntegrityCheck)
+    }
+
+    fn collection(&self, spec: C4CollectionSpec) -> Result<NonNull<C4Collection>> {
+        let mut c4err = c4error_init();
+        let collection = unsafe { c4db_getCollection(self.inner.0.as_ptr(), spec, &mut c4err) };
+        match NonNull::new(collection) {
+            Some(x) => Ok(x),
+            None if c4err.code != 0 => Err(c4err.into()),
+            None => Err(Error::LogicError("collection not found".into())),
+        }
+    }
+
+    fn maintenance(&self, ty: C4MaintenanceType) -> Result<()> {
+        let mut c4err = c4error_init();
+        if unsafe { c4db_maintenance(self.inner.0.as_ptr(), ty, &mut c4err) } {
+            Ok(())
+        } else {
+            Err(c4err.into())
+        }
+    }
+
+    /// Returns the database's shared keys, that used to encode
+    /// dictionaries keys of documents' bodies. It can be used
+    /// to decode fleece data copied out of database,
+    /// see `serde_fleece::from_slice_with_shared_keys`
+    /// and `ReplicatedDoc::decode_with_shared_keys`.
+    /// Shared keys are retained, so they stay valid after database is closed
+    pub fn fleece_shared_keys(&self) -> Result<SharedKeys> {
+        unsafe { SharedKeys::retain(c4db_getFLSharedKeys(self.inner.0.as_ptr())) }
+            .ok_or_else(|| Error::LogicError("Database has no shared keys".into()))
+    }
+
     /// Returns the name of the database, as given to `c4db_openNamed`.
     /// This is the filename _without_ the ".cblite2" extension.
     #[inline]
@@ -336,6 +605,16 @@ impl Database {
             .try_into()
             .map_err(|_| Error::InvalidUtf8)
     }
+    /// Returns the configuration the database was opened with
+    #[inline]
+    pub fn config(&self) -> &C4DatabaseConfig2 {
+        unsafe { &*c4db_getConfig2(self.inner.0.as_ptr()) }
+    }
+    /// Returns the flags the database was opened with
+    #[inline]
+    pub fn flags(&self) -> DatabaseFlags {
+        DatabaseFlags::from_bits_truncate(self.config().flags.0)
+    }
 
     pub(crate) fn do_internal_get(
         &self,
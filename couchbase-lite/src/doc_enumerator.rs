@@ -1,9 +1,10 @@
 use crate::{
-    document::{C4DocumentOwner, Document},
+    document::{C4DocumentOwner, Document, DocumentFlags},
     error::{c4error_init, Error, Result},
     ffi::{
-        c4db_enumerateAllDocs, c4enum_free, c4enum_getDocument, c4enum_getDocumentInfo,
-        c4enum_next, C4DocEnumerator, C4DocumentInfo, C4EnumeratorFlags, C4EnumeratorOptions,
+        c4coll_enumerateAllDocs, c4db_enumerateAllDocs, c4enum_free, c4enum_getDocument,
+        c4enum_getDocumentInfo, c4enum_next, C4Collection, C4DocEnumerator, C4DocumentInfo,
+        C4EnumeratorFlags, C4EnumeratorOptions,
     },
     Database,
 };
@@ -40,6 +41,25 @@ impl<'a, 'b> DocumentInfo<'_, '_> {
     pub fn doc_id(&self) -> &str {
         unsafe { str::from_utf8_unchecked(self.inner.docID.into()) }
     }
+    /// Revision ID of document's current revision
+    #[inline]
+    pub fn revision_id(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(self.inner.revID.into()) }
+    }
+    /// Sequence number of document's last modification
+    #[inline]
+    pub fn sequence(&self) -> u64 {
+        self.inner.sequence
+    }
+    #[inline]
+    pub fn flags(&self) -> DocumentFlags {
+        DocumentFlags::from_bits_truncate(self.inner.flags.0)
+    }
+    /// Size in bytes of document's current revision body
+    #[inline]
+    pub fn body_size(&self) -> u64 {
+        self.inner.bodySize
+    }
 }
 
 impl<'a> DocEnumerator<'a> {
@@ -61,6 +81,27 @@ impl<'a> DocEnumerator<'a> {
             .ok_or_else(|| c4err.into())
     }
 
+    pub(crate) fn enumerate_collection_docs(
+        db: &'a Database,
+        collection: NonNull<C4Collection>,
+        flags: DocEnumeratorFlags,
+    ) -> Result<DocEnumerator<'a>> {
+        let mut c4err = c4error_init();
+        let opts = C4EnumeratorOptions {
+            flags: C4EnumeratorFlags(flags.bits()),
+        };
+        let enum_ptr = unsafe { c4coll_enumerateAllDocs(collection.as_ptr(), &opts, &mut c4err) };
+        NonNull::new(enum_ptr)
+            .map(|inner| DocEnumerator {
+                _db: db,
+                inner,
+                reach_end: false,
+            })
+            .ok_or_else(|| c4err.into())
+    }
+
+    /// Return document's metadata (id, revision id, sequence, flags),
+    /// it doesn't load document's body, so it is cheaper then `get_doc`
     #[inline]
     pub fn get_doc_info(&self) -> Result<Option<DocumentInfo>> {
         let mut di = MaybeUninit::<C4DocumentInfo>::uninit();
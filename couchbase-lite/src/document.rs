@@ -1,14 +1,19 @@
 use crate::{
     error::{c4error_init, Error, Result},
     ffi::{
-        c4doc_getRevisionBody, c4doc_loadRevisionBody, c4doc_release, c4rev_getGeneration,
-        C4Document, C4DocumentFlags, C4Revision, FLSliceResult,
+        c4doc_createFleeceDoc, c4doc_getRevisionBody, c4doc_getRevisionHistory,
+        c4doc_hasRevisionBody, c4doc_loadRevisionBody, c4doc_release, c4doc_selectCurrentRevision,
+        c4doc_selectNextLeafRevision, c4doc_selectRevision, c4rev_getGeneration, C4DocContentLevel,
+        C4Document, C4DocumentFlags, C4Revision, C4RevisionFlags, C4SequenceNumber, FLDoc_GetRoot,
+        FLDoc_Release, FLSliceResult, _FLDoc, _FLEncoder,
     },
 };
 use bitflags::bitflags;
-use serde::{de::DeserializeOwned, Serialize};
-use serde_fleece::{to_fl_slice_result_with_encoder, FlEncoderSession};
-use std::{os::raw::c_uint, ptr::NonNull, str};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_fleece::{
+    json_to_fleece_with_encoder, to_fl_slice_result_with_encoder, FlEncoderSession, NonNullConst,
+};
+use std::{borrow::Borrow, os::raw::c_uint, ptr, ptr::NonNull, str};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -32,17 +37,48 @@ bitflags! {
     }
 }
 
+/// How much of document's content should be loaded from database
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentLevel {
+    /// Only metadata (id, revision id, sequence, flags)
+    Metadata,
+    /// Metadata and body of current revision
+    CurrentRev,
+    /// All revisions
+    All,
+}
+
+impl From<ContentLevel> for C4DocContentLevel {
+    fn from(level: ContentLevel) -> Self {
+        use ContentLevel::*;
+        match level {
+            Metadata => C4DocContentLevel::kDocGetMetadata,
+            CurrentRev => C4DocContentLevel::kDocGetCurrentRev,
+            All => C4DocContentLevel::kDocGetAll,
+        }
+    }
+}
+
 impl Document {
     #[inline]
     pub fn new<T>(data: &T, enc: FlEncoderSession) -> Result<Self>
     where
         T: Serialize,
+    {
+        Self::new_with_encoder(data, enc)
+    }
+    /// Same as `new`, but `enc` can be borrowed encoder session,
+    /// like one from `Transaction::encoder`
+    pub fn new_with_encoder<T, E>(data: &T, enc: E) -> Result<Self>
+    where
+        T: Serialize,
+        E: Borrow<NonNull<_FLEncoder>>,
     {
         let unsaved_body = Some(to_fl_slice_result_with_encoder(data, enc)?);
         Ok(Self {
             inner: None,
             unsaved_body,
-            id: Uuid::new_v4().hyphenated().to_string(),
+            id: Self::generate_id(),
         })
     }
     #[inline]
@@ -50,6 +86,15 @@ impl Document {
     where
         S: Into<String>,
         T: Serialize,
+    {
+        Self::new_with_id_with_encoder(doc_id, data, enc)
+    }
+    /// Same as `new_with_id`, but `enc` can be borrowed encoder session
+    pub fn new_with_id_with_encoder<S, T, E>(doc_id: S, data: &T, enc: E) -> Result<Self>
+    where
+        S: Into<String>,
+        T: Serialize,
+        E: Borrow<NonNull<_FLEncoder>>,
     {
         let unsaved_body = Some(to_fl_slice_result_with_encoder(data, enc)?);
         Ok(Self {
@@ -66,6 +111,35 @@ impl Document {
             unsaved_body: Some(fleece_data),
         }
     }
+    /// Create document with random ID from JSON value
+    #[cfg(feature = "serde_json")]
+    #[inline]
+    pub fn from_json_value(value: &serde_json::Value, enc: FlEncoderSession) -> Result<Self> {
+        Self::new(value, enc)
+    }
+    /// Create document with body parsed from JSON text,
+    /// JSON converted into fleece directly, without intermediate Rust types
+    #[inline]
+    pub fn from_json_str<S: Into<String>>(
+        doc_id: S,
+        json: &str,
+        enc: FlEncoderSession,
+    ) -> Result<Self> {
+        Self::from_json_str_with_encoder(doc_id, json, enc)
+    }
+    /// Same as `from_json_str`, but `enc` can be borrowed encoder session
+    pub fn from_json_str_with_encoder<S, E>(doc_id: S, json: &str, enc: E) -> Result<Self>
+    where
+        S: Into<String>,
+        E: Borrow<NonNull<_FLEncoder>>,
+    {
+        let unsaved_body = Some(json_to_fleece_with_encoder(json.as_bytes(), enc)?);
+        Ok(Self {
+            inner: None,
+            id: doc_id.into(),
+            unsaved_body,
+        })
+    }
     /// return the document's ID
     #[inline]
     pub fn id(&self) -> &str {
@@ -87,16 +161,57 @@ impl Document {
         let x: T = serde_fleece::from_slice(body)?;
         Ok(x)
     }
+    /// Decode body of document, in contrast to `decode_body`
+    /// result can borrow data (for example `&str`) from document
+    pub fn decode_body_borrowed<'a, T: Deserialize<'a>>(&'a self) -> Result<T> {
+        if let Some(slice) = self.unsaved_body.as_ref().map(FLSliceResult::as_bytes) {
+            let x: T = serde_fleece::from_slice(slice)?;
+            return Ok(x);
+        }
+        let inner: &C4DocumentOwner = self.inner.as_ref().ok_or_else(|| {
+            Error::LogicError(format!(
+                "Document {} have no underlying C4Document",
+                self.id
+            ))
+        })?;
+        let body = inner.load_body()?;
+        let x: T = serde_fleece::from_slice(body)?;
+        Ok(x)
+    }
     /// Update internal buffer with data, you need save document
     /// to database to make this change permanent
+    #[inline]
     pub fn update_body<T>(&mut self, data: &T, enc: FlEncoderSession) -> Result<()>
     where
         T: Serialize,
+    {
+        self.update_body_with_encoder(data, enc)
+    }
+    /// Same as `update_body`, but `enc` can be borrowed encoder session
+    pub fn update_body_with_encoder<T, E>(&mut self, data: &T, enc: E) -> Result<()>
+    where
+        T: Serialize,
+        E: Borrow<NonNull<_FLEncoder>>,
     {
         let body = to_fl_slice_result_with_encoder(data, enc)?;
         self.unsaved_body = Some(body);
         Ok(())
     }
+    /// Update internal buffer with already encoded fleece data,
+    /// you need save document to database to make this change permanent
+    #[inline]
+    pub fn update_body_fleece(&mut self, fleece_data: FLSliceResult) {
+        self.unsaved_body = Some(fleece_data);
+    }
+    /// Raw fleece body of document: not yet saved body if it was set,
+    /// otherwise body of selected revision, if it is loaded.
+    /// Can be used to copy stored representation without decoding
+    pub fn body_fleece(&self) -> Option<&[u8]> {
+        if let Some(slice) = self.unsaved_body.as_ref() {
+            return Some(slice.as_bytes());
+        }
+        self.inner.as_ref()?.revision_body()
+    }
 
     /// Returns a document's current sequence in the local database.
     /// This number increases every time the document is saved, and a more recently saved document
@@ -140,6 +255,109 @@ impl Document {
         self.inner.as_ref().map(|x| x.exists()).unwrap_or(false)
     }
 
+    /// Check if document's current revision is deleted
+    #[inline]
+    pub fn is_deleted(&self) -> bool {
+        self.has_flag(DocumentFlags::DELETED)
+    }
+
+    /// Check if document is in conflict
+    #[inline]
+    pub fn is_conflicted(&self) -> bool {
+        self.has_flag(DocumentFlags::CONFLICTED)
+    }
+
+    /// Check if document's current revision has attachments
+    #[inline]
+    pub fn has_attachments(&self) -> bool {
+        self.has_flag(DocumentFlags::HAS_ATTACHMENTS)
+    }
+
+    #[inline]
+    fn has_flag(&self, flag: DocumentFlags) -> bool {
+        self.flags().map(|x| x.contains(flag)).unwrap_or(false)
+    }
+
+    /// Returns all leaf revisions of the document, the first one is the current
+    /// revision, the rest are revisions in conflict with it (if any).
+    /// Can be used to build custom merge of conflicting revisions.
+    /// `&mut self` because it walks through leaves by changing selected revision,
+    /// after return the current revision is selected.
+    pub fn conflicting_revisions(&mut self) -> Result<Vec<RevisionInfo>> {
+        let inner: &C4DocumentOwner = self.inner.as_ref().ok_or_else(|| {
+            Error::LogicError(format!(
+                "Document {} have no underlying C4Document",
+                self.id
+            ))
+        })?;
+        unsafe { c4doc_selectCurrentRevision(inner.0.as_ptr()) };
+        let mut ret = vec![RevisionInfo::from_selected(inner)?];
+        let mut c4err = c4error_init();
+        while unsafe { c4doc_selectNextLeafRevision(inner.0.as_ptr(), true, true, &mut c4err) } {
+            match RevisionInfo::from_selected(inner) {
+                Ok(rev) => ret.push(rev),
+                Err(err) => {
+                    unsafe { c4doc_selectCurrentRevision(inner.0.as_ptr()) };
+                    return Err(err);
+                }
+            }
+        }
+        unsafe { c4doc_selectCurrentRevision(inner.0.as_ptr()) };
+        if c4err.code == 0 {
+            Ok(ret)
+        } else {
+            Err(c4err.into())
+        }
+    }
+
+    /// Returns IDs of revisions from the selected revision (current by default)
+    /// back to the oldest one, that still kept in revision tree
+    pub fn revision_history(&self) -> Result<Vec<String>> {
+        let inner: &C4DocumentOwner = self.inner.as_ref().ok_or_else(|| {
+            Error::LogicError(format!(
+                "Document {} have no underlying C4Document",
+                self.id
+            ))
+        })?;
+        let history =
+            unsafe { c4doc_getRevisionHistory(inner.0.as_ptr(), c_uint::MAX, ptr::null(), 0) };
+        let history = str::from_utf8(history.as_bytes()).map_err(|_| Error::InvalidUtf8)?;
+        Ok(history
+            .split(',')
+            .filter(|rev_id| !rev_id.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Select revision with `rev_id`, after that `decode_body`
+    /// and `sequence` return data of this revision.
+    /// Body of old revisions can be unavailable, if it was compacted away
+    pub fn select_revision(&mut self, rev_id: &str) -> Result<()> {
+        let inner: &C4DocumentOwner = self.inner.as_ref().ok_or_else(|| {
+            Error::LogicError(format!(
+                "Document {} have no underlying C4Document",
+                self.id
+            ))
+        })?;
+        let mut c4err = c4error_init();
+        if unsafe { c4doc_selectRevision(inner.0.as_ptr(), rev_id.into(), true, &mut c4err) } {
+            Ok(())
+        } else {
+            Err(c4err.into())
+        }
+    }
+
+    /// Select current revision back, after `select_revision`
+    pub fn select_current_revision(&mut self) {
+        if let Some(inner) = self.inner.as_ref() {
+            unsafe { c4doc_selectCurrentRevision(inner.0.as_ptr()) };
+        }
+    }
+
+    pub(crate) fn generate_id() -> String {
+        Uuid::new_v4().hyphenated().to_string()
+    }
+
     pub(crate) fn new_internal<S>(inner: C4DocumentOwner, doc_id: S) -> Self
     where
         S: Into<String>,
@@ -155,6 +373,81 @@ impl Document {
     }
 }
 
+/// Information about one revision of document
+#[derive(Debug)]
+pub struct RevisionInfo {
+    rev_id: String,
+    flags: C4RevisionFlags,
+    sequence: C4SequenceNumber,
+    body: Option<FleeceDoc>,
+}
+
+/// Fleece document with body of revision, keeps reference
+/// to database's shared keys, so body can be decoded
+#[derive(Debug)]
+struct FleeceDoc(NonNull<_FLDoc>);
+
+impl Drop for FleeceDoc {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { FLDoc_Release(self.0.as_ptr()) };
+    }
+}
+
+impl RevisionInfo {
+    fn from_selected(doc: &C4DocumentOwner) -> Result<Self> {
+        let rev = doc.selected_revision();
+        let rev_id = str::from_utf8(rev.revID.as_fl_slice().into())
+            .map_err(|_| Error::InvalidUtf8)?
+            .to_string();
+        let mut c4err = c4error_init();
+        let has_body = unsafe {
+            c4doc_hasRevisionBody(doc.0.as_ptr())
+                || c4doc_loadRevisionBody(doc.0.as_ptr(), &mut c4err)
+        };
+        let body = if has_body {
+            NonNull::new(unsafe { c4doc_createFleeceDoc(doc.0.as_ptr()) }).map(FleeceDoc)
+        } else {
+            None
+        };
+        Ok(Self {
+            rev_id,
+            flags: rev.flags,
+            sequence: rev.sequence,
+            body,
+        })
+    }
+    #[inline]
+    pub fn rev_id(&self) -> &str {
+        &self.rev_id
+    }
+    #[inline]
+    pub fn flags(&self) -> C4RevisionFlags {
+        self.flags
+    }
+    #[inline]
+    pub fn sequence(&self) -> C4SequenceNumber {
+        self.sequence
+    }
+    /// Is this revision a deletion (tombstone)
+    #[inline]
+    pub fn is_deleted(&self) -> bool {
+        (self.flags & C4RevisionFlags::kRevDeleted) != C4RevisionFlags(0)
+    }
+    /// Decode body of revision, return error if body is not available,
+    /// for example because of revision is deleted or body was compacted away
+    pub fn decode_body<'a, T: Deserialize<'a>>(&'a self) -> Result<T> {
+        let body = self
+            .body
+            .as_ref()
+            .ok_or_else(|| Error::LogicError(format!("Revision {} have no body", self.rev_id)))?;
+        let root = NonNullConst::new(unsafe { FLDoc_GetRoot(body.0.as_ptr()) })
+            .ok_or_else(|| Error::LogicError(format!("Revision {} have no body", self.rev_id)))?;
+        let x: T = serde_fleece::from_fl_value(root)?;
+        Ok(x)
+    }
+}
+
 #[repr(transparent)]
 #[derive(Debug)]
 pub(crate) struct C4DocumentOwner(pub(crate) NonNull<C4Document>);
@@ -169,6 +462,9 @@ impl C4DocumentOwner {
     pub(crate) fn exists(&self) -> bool {
         (self.flags() & C4DocumentFlags::kDocExists) == C4DocumentFlags::kDocExists
     }
+    pub(crate) fn is_deleted(&self) -> bool {
+        (self.flags() & C4DocumentFlags::kDocDeleted) == C4DocumentFlags::kDocDeleted
+    }
     fn flags(&self) -> C4DocumentFlags {
         unsafe { self.0.as_ref().flags }
     }
@@ -191,6 +487,14 @@ impl C4DocumentOwner {
     pub(crate) fn generation(rev_id: &[u8]) -> c_uint {
         unsafe { c4rev_getGeneration(rev_id.into()) }
     }
+    pub(crate) fn revision_body(&self) -> Option<&[u8]> {
+        let body = unsafe { c4doc_getRevisionBody(self.0.as_ptr()) };
+        if body.buf.is_null() {
+            None
+        } else {
+            Some(body.into())
+        }
+    }
     pub(crate) fn load_body(&self) -> Result<&[u8]> {
         let mut c4err = c4error_init();
         if unsafe { c4doc_loadRevisionBody(self.0.as_ptr(), &mut c4err) } {
@@ -21,7 +21,18 @@ pub enum Error {
     },
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SerdeFleece(err) => Some(err),
+            Error::NulError(err) => Some(err),
+            Error::C4Error(_)
+            | Error::InvalidUtf8
+            | Error::LogicError(_)
+            | Error::InvalidQuery { .. } => None,
+        }
+    }
+}
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
 
@@ -109,6 +120,12 @@ impl From<serde_fleece::Error> for Error {
     }
 }
 
+impl From<std::str::Utf8Error> for Error {
+    fn from(_: std::str::Utf8Error) -> Self {
+        Error::InvalidUtf8
+    }
+}
+
 impl From<std::ffi::NulError> for Error {
     fn from(err: std::ffi::NulError) -> Self {
         Error::NulError(err)
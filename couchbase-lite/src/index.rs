@@ -6,11 +6,13 @@ use crate::{
         _FLDict,
     },
     value::{ValueRef, ValueRefArray},
+    QueryLanguage,
 };
 use fallible_streaming_iterator::FallibleStreamingIterator;
 use serde_fleece::NonNullConst;
 
 /// Database's index types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IndexType {
     /// Regular index of property value
     ValueIndex,
@@ -18,10 +20,43 @@ pub enum IndexType {
     FullTextIndex,
     /// Index of array values, for use with UNNEST
     ArrayIndex,
-    /// Index of prediction() results (Enterprise Edition only)
+    /// Index of prediction() results (Enterprise Edition only).
+    /// Bundled couchbase-lite-core is Community Edition and has no API
+    /// to register prediction models, so `Database::create_index` rejects
+    /// this type. It is only reported for indexes created by other tools.
     PredictiveIndex,
 }
 
+impl From<IndexType> for C4IndexType {
+    fn from(index_type: IndexType) -> Self {
+        use IndexType::*;
+        match index_type {
+            ValueIndex => C4IndexType::kC4ValueIndex,
+            FullTextIndex => C4IndexType::kC4FullTextIndex,
+            ArrayIndex => C4IndexType::kC4ArrayIndex,
+            PredictiveIndex => C4IndexType::kC4PredictiveIndex,
+        }
+    }
+}
+
+impl TryFrom<C4IndexType> for IndexType {
+    type Error = Error;
+
+    fn try_from(index_type: C4IndexType) -> Result<Self> {
+        use IndexType::*;
+        match index_type {
+            C4IndexType::kC4ValueIndex => Ok(ValueIndex),
+            C4IndexType::kC4FullTextIndex => Ok(FullTextIndex),
+            C4IndexType::kC4ArrayIndex => Ok(ArrayIndex),
+            C4IndexType::kC4PredictiveIndex => Ok(PredictiveIndex),
+            _ => Err(Error::LogicError(format!(
+                "Unknown index type {}",
+                index_type.0
+            ))),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct IndexOptions<'a> {
     /// Dominant language of text to be indexed; setting this enables word stemming, i.e.
@@ -51,6 +86,55 @@ pub struct IndexOptions<'a> {
     /// To provide a custom list of words, use the words in lowercase
     /// separated by spaces.
     pub stop_words: Option<&'a [&'a str]>,
+    /// Path of array property for `IndexType::ArrayIndex`, for example "tags"
+    /// or "address.phones", index expressions then are relative to array's items.
+    /// couchbase-lite-core treats the first expression of array index as
+    /// path to array, so this path is prepended to index expressions.
+    pub unnest_path: Option<&'a str>,
+}
+
+/// Build expression of array index: path to array and then expressions of array's items
+pub(crate) fn array_index_expression(
+    unnest_path: &str,
+    expression: &str,
+    language: QueryLanguage,
+) -> Result<String> {
+    let unnest_path = unnest_path.trim_start_matches('.');
+    if unnest_path.is_empty() || unnest_path.contains(['"', '\\']) {
+        return Err(Error::LogicError(format!(
+            "Invalid array index path '{unnest_path}'"
+        )));
+    }
+    let expression = expression.trim();
+    match language {
+        QueryLanguage::kC4JSONQuery => {
+            let items = if expression.is_empty() {
+                ""
+            } else {
+                expression
+                    .strip_prefix('[')
+                    .and_then(|x| x.strip_suffix(']'))
+                    .ok_or_else(|| {
+                        Error::LogicError(format!(
+                            "Array index expression should be JSON array, got '{expression}'"
+                        ))
+                    })?
+                    .trim()
+            };
+            if items.is_empty() {
+                Ok(format!(r#"[[".{unnest_path}"]]"#))
+            } else {
+                Ok(format!(r#"[[".{unnest_path}"], {items}]"#))
+            }
+        }
+        QueryLanguage::kC4N1QLQuery => {
+            if expression.is_empty() {
+                Ok(unnest_path.to_string())
+            } else {
+                Ok(format!("{unnest_path}, {expression}"))
+            }
+        }
+    }
 }
 
 pub(crate) struct DbIndexesListIterator {
@@ -99,6 +183,13 @@ impl IndexInfo {
     pub fn type_(&self) -> C4IndexType {
         self.type_
     }
+    /// Type of index, in contrast to `type_` returns error
+    /// for index types unknown to this crate
+    #[inline]
+    pub fn index_type(&self) -> Result<IndexType> {
+        self.type_.try_into()
+    }
+    /// Indexed expression, in query language used during index creation
     #[inline]
     pub fn expr_as_str(&self) -> Result<&str> {
         self.expr
@@ -179,3 +270,27 @@ impl FallibleStreamingIterator for DbIndexesListIterator {
         self.cur_val.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_array_index_expression() {
+        assert_eq!(
+            r#"[[".tags"]]"#,
+            array_index_expression("tags", "[]", QueryLanguage::kC4JSONQuery).unwrap()
+        );
+        assert_eq!(
+            r#"[[".a.phones"], [".number"]]"#,
+            array_index_expression(".a.phones", r#"[[".number"]]"#, QueryLanguage::kC4JSONQuery)
+                .unwrap()
+        );
+        assert!(array_index_expression("tags", "[\".x\"", QueryLanguage::kC4JSONQuery).is_err());
+        assert_eq!(
+            "tags, name",
+            array_index_expression("tags", "name", QueryLanguage::kC4N1QLQuery).unwrap()
+        );
+        assert!(array_index_expression("", "", QueryLanguage::kC4N1QLQuery).is_err());
+    }
+}
@@ -51,24 +51,33 @@ mod error;
 mod index;
 mod log_reroute;
 mod observer;
+pub mod prelude;
 mod query;
 mod replicator;
 mod transaction;
 mod value;
 
 pub use crate::{
-    conflict_resolver::resolve_conflict,
+    conflict_resolver::{resolve_conflict, resolve_conflict_with, ConflictResolution, MergedBody},
     database::{Database, DatabaseConfig, DatabaseFlags},
     doc_enumerator::{DocEnumeratorFlags, DocumentInfo},
-    document::{Document, DocumentFlags},
+    document::{ContentLevel, Document, DocumentFlags, RevisionInfo},
     error::Error,
     fallible_streaming_iterator::FallibleStreamingIterator,
-    index::IndexType,
-    replicator::{Replicator, ReplicatorAuthentication, ReplicatorParameters, ReplicatorState},
-    value::{ValueRef, ValueRefArray},
+    index::{IndexOptions, IndexType},
+    query::{OwnedRow, Row},
+    replicator::{
+        BackoffConfig, DocumentEnded, ReplicatedDoc, Replicator, ReplicatorAuthentication,
+        ReplicatorParameters, ReplicatorState, SocketFactoryImpl, SocketHandle,
+    },
+    value::{ValueRef, ValueRefArray, ValueRefDict},
 };
 pub use couchbase_lite_core_sys as ffi;
 pub use fallible_streaming_iterator;
-pub use ffi::{C4CollectionSpec, C4DocumentEnded, C4QueryLanguage as QueryLanguage, C4String};
+pub use ffi::{
+    C4CollectionSpec, C4DocumentEnded, C4FullTextMatch, C4QueryLanguage as QueryLanguage, C4String,
+};
+#[cfg(feature = "use-rustls")]
+pub use rustls;
 
 pub use serde_fleece;
@@ -1,14 +1,21 @@
 use crate::{
+    document::{C4DocumentOwner, Document},
     error::{c4error_init, Error, Result},
     ffi::{
         c4db_getCollection, c4dbobs_createOnCollection, c4dbobs_free, c4dbobs_getChanges,
         c4dbobs_releaseChanges, kC4DefaultCollectionSpec, C4CollectionChange, C4CollectionObserver,
-        C4RevisionFlags,
+        C4DocContentLevel, C4RevisionFlags, C4SequenceNumber,
     },
     Database,
 };
 use log::error;
-use std::{mem::MaybeUninit, os::raw::c_void, panic::catch_unwind, process::abort, ptr::NonNull};
+use std::{
+    collections::VecDeque,
+    os::raw::{c_uint, c_void},
+    panic::catch_unwind,
+    process::abort,
+    ptr::NonNull,
+};
 
 pub(crate) struct DatabaseObserver {
     inner: NonNull<C4CollectionObserver>,
@@ -81,17 +88,26 @@ impl DatabaseObserver {
     pub(crate) fn match_obs_ptr(&self, obs_ptr: usize) -> bool {
         self.inner.as_ptr() as usize == obs_ptr
     }
-    pub(crate) fn changes_iter(&self) -> DbChangesIter {
-        DbChangesIter { obs: self }
+    pub(crate) fn changes_iter(&self, batch_size: u32) -> DbChangesIter {
+        DbChangesIter {
+            obs: self,
+            batch_size,
+            batch: VecDeque::new(),
+        }
     }
 }
 
-unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
+pub(crate) unsafe extern "C" fn free_boxed_value<T>(p: *mut c_void) {
     drop(Box::from_raw(p as *mut T));
 }
 
+/// How many changes `Database::observed_changes` reads per `c4dbobs_getChanges` call
+pub(crate) const DEFAULT_OBSERVED_CHANGES_BATCH_SIZE: u32 = 100;
+
 pub(crate) struct DbChangesIter<'obs> {
     obs: &'obs DatabaseObserver,
+    batch_size: u32,
+    batch: VecDeque<DbChange>,
 }
 
 #[derive(Debug)]
@@ -129,6 +145,28 @@ impl DbChange {
     pub fn revision_flags(&self) -> C4RevisionFlags {
         self.inner.flags
     }
+    /// Changed revision is in conflict with another one
+    #[inline]
+    pub fn is_conflict(&self) -> bool {
+        (self.inner.flags & C4RevisionFlags::kRevIsConflict) != C4RevisionFlags(0)
+    }
+    /// Sequence number of changed revision
+    #[inline]
+    pub fn sequence(&self) -> C4SequenceNumber {
+        self.inner.sequence
+    }
+    /// Generation number of changed revision
+    #[inline]
+    pub fn generation(&self) -> c_uint {
+        C4DocumentOwner::generation(self.inner.revID.as_fl_slice().into())
+    }
+    /// Load current state of changed document, `None` if document was purged.
+    /// Use `ObserverdChangesIter::database` to get `db` during iteration
+    pub fn get_doc(&self, db: &Database) -> Result<Option<Document>> {
+        let doc_id = self.doc_id()?;
+        let doc = db.do_internal_get_opt(doc_id, true, C4DocContentLevel::kDocGetCurrentRev)?;
+        Ok(doc.map(|x| Document::new_internal(x, doc_id)))
+    }
 }
 
 impl Drop for DbChange {
@@ -141,26 +179,36 @@ impl Drop for DbChange {
 impl<'obs> Iterator for DbChangesIter<'obs> {
     type Item = DbChange;
 
-    #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        let mut item = MaybeUninit::<C4CollectionChange>::uninit();
-        let observation =
-            unsafe { c4dbobs_getChanges(self.obs.inner.as_ptr(), item.as_mut_ptr(), 1) };
-        if observation.numChanges > 0 {
-            let item = unsafe { item.assume_init() };
-            Some(DbChange {
-                inner: item,
-                external: observation.external,
-            })
-        } else {
-            None
+        if let Some(item) = self.batch.pop_front() {
+            return Some(item);
         }
+        let mut items = Vec::<C4CollectionChange>::with_capacity(self.batch_size as usize);
+        let observation = unsafe {
+            c4dbobs_getChanges(self.obs.inner.as_ptr(), items.as_mut_ptr(), self.batch_size)
+        };
+        unsafe { items.set_len(observation.numChanges as usize) };
+        self.batch.extend(items.into_iter().map(|inner| DbChange {
+            inner,
+            external: observation.external,
+        }));
+        self.batch.pop_front()
     }
 }
 
 pub struct ObserverdChangesIter<'db> {
     pub(crate) db: &'db Database,
     pub(crate) obs_it: Option<DbChangesIter<'db>>,
+    pub(crate) batch_size: u32,
+}
+
+impl<'db> ObserverdChangesIter<'db> {
+    /// Database which changes are observed, can be used together with
+    /// `DbChange::get_doc` while iterating
+    #[inline]
+    pub fn database(&self) -> &'db Database {
+        self.db
+    }
 }
 
 impl<'db> Iterator for ObserverdChangesIter<'db> {
@@ -193,7 +241,7 @@ impl<'db> Iterator for ObserverdChangesIter<'db> {
                 .iter()
                 .find(|obs| obs.match_obs_ptr(obs_ptr));
             if let Some(obs) = obs {
-                self.obs_it = Some(obs.changes_iter());
+                self.obs_it = Some(obs.changes_iter(self.batch_size));
             }
         }
     }
@@ -0,0 +1,13 @@
+//! Commonly used types, FFI enums and traits, to use with
+//! `use couchbase_lite::prelude::*;`
+
+pub use crate::{
+    ffi::{
+        C4CollectionSpec, C4DocumentEnded, C4DocumentFlags, C4ReplicatorMode, C4RevisionFlags,
+        C4String, FLDict,
+    },
+    BackoffConfig, ContentLevel, Database, DatabaseFlags, DocEnumeratorFlags, Document,
+    DocumentEnded, DocumentFlags, Error, FallibleStreamingIterator, IndexType, QueryLanguage,
+    ReplicatedDoc, Replicator, ReplicatorAuthentication, ReplicatorParameters, ReplicatorState,
+    ValueRef,
+};
@@ -1,25 +1,49 @@
 use crate::{
     error::{c4error_init, Error, Result},
     ffi::{
-        c4query_new2, c4query_release, c4query_run, c4query_setParameters, c4queryenum_next,
-        c4queryenum_release, C4Query, C4QueryEnumerator, C4String, FLArrayIterator_GetCount,
-        FLArrayIterator_GetValueAt, FLStringResult, FLValue,
+        c4query_columnCount, c4query_columnTitle, c4query_fullTextMatched, c4query_new2,
+        c4query_release, c4query_run, c4query_setParameters, c4queryenum_next, c4queryenum_release,
+        c4queryobs_create, c4queryobs_free, c4queryobs_getEnumerator, c4queryobs_setEnabled,
+        C4FullTextMatch, C4Query, C4QueryEnumerator, C4QueryObserver, C4String,
+        FLArrayIterator_GetCount, FLArrayIterator_GetValueAt, FLArray_Count, FLArray_Get, FLDict,
+        FLDictIterator, FLDictIterator_Begin, FLDictIterator_End, FLDictIterator_GetKeyString,
+        FLDictIterator_GetValue, FLDictIterator_Next, FLEncoder_BeginArray, FLEncoder_BeginDict,
+        FLEncoder_EndArray, FLEncoder_EndDict, FLEncoder_Finish, FLEncoder_Free, FLEncoder_New,
+        FLEncoder_WriteKey, FLEncoder_WriteValue, FLError, FLSliceResult, FLStringResult, FLTrust,
+        FLValue, FLValue_AsArray, FLValue_AsDict, FLValue_FromData,
     },
+    observer::free_boxed_value,
     value::{FromValueRef, ValueRef},
     Database, QueryLanguage,
 };
 use fallible_streaming_iterator::FallibleStreamingIterator;
+use log::error;
 use serde::Serialize;
 use serde_fleece::NonNullConst;
-use std::ptr::{self, NonNull};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    mem::MaybeUninit,
+    ops::Index,
+    os::raw::c_void,
+    panic::catch_unwind,
+    process::abort,
+    ptr::{self, NonNull},
+    slice,
+};
 
 pub struct Query<'db> {
     _db: &'db Database,
     inner: NonNull<C4Query>,
+    observer: Option<QueryObserver>,
+    /// currently bound parameters, name -> fleece encoded value
+    parameters: RefCell<BTreeMap<String, FLSliceResult>>,
 }
 
 impl Drop for Query<'_> {
     fn drop(&mut self) {
+        // observer should be freed before query
+        self.observer.take();
         unsafe { c4query_release(self.inner.as_ptr()) };
     }
 }
@@ -43,7 +67,12 @@ impl Query<'_> {
         };
 
         NonNull::new(query)
-            .map(|inner| Query { _db: db, inner })
+            .map(|inner| Query {
+                _db: db,
+                inner,
+                observer: None,
+                parameters: RefCell::new(BTreeMap::new()),
+            })
             .ok_or_else(|| c4err.into())
     }
 
@@ -54,23 +83,89 @@ impl Query<'_> {
         parameters: std::result::Result<FLStringResult, serde_fleece::Error>,
     ) -> Result<()> {
         let params = parameters?;
-        unsafe {
-            c4query_setParameters(self.inner.as_ptr(), params.as_fl_slice());
-        }
-        Ok(())
+        self.replace_parameters(params)
     }
 
+    /// Bind parameters (`$name` inside query), `parameters` should be
+    /// serialized as dictionary. Dynamic values, including `LIMIT` and
+    /// `OFFSET` (`LIMIT $limit OFFSET $offset`), should be bound this way
+    /// instead of formatting them into query text
     pub fn set_parameters<T>(&self, parameters: &T) -> Result<()>
     where
         T: Serialize,
     {
         let param_string = serde_fleece::to_fl_slice_result(parameters)?;
+        self.replace_parameters(param_string)
+    }
+
+    /// Bind one parameter, in contrast to `set_parameters`
+    /// already bound parameters are kept
+    pub fn set_parameter<T>(&self, name: &str, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let value = serde_fleece::to_fl_slice_result(value)?;
+        let mut parameters = self.parameters.borrow_mut();
+        parameters.insert(name.to_string(), value);
+        let params = encode_parameters(&parameters)?;
+        unsafe {
+            c4query_setParameters(self.inner.as_ptr(), params.as_fl_slice());
+        }
+        Ok(())
+    }
+
+    /// Unbind all parameters, so reused query doesn't get values
+    /// from previous run
+    pub fn clear_parameters(&self) -> Result<()> {
+        let mut parameters = self.parameters.borrow_mut();
+        parameters.clear();
+        let params = encode_parameters(&parameters)?;
+        unsafe {
+            c4query_setParameters(self.inner.as_ptr(), params.as_fl_slice());
+        }
+        Ok(())
+    }
+
+    fn replace_parameters(&self, params: FLSliceResult) -> Result<()> {
+        let dict = unsafe {
+            FLValue_AsDict(FLValue_FromData(
+                params.as_fl_slice(),
+                FLTrust::kFLUntrusted,
+            ))
+        };
+        if dict.is_null() {
+            return Err(Error::LogicError(
+                "Query parameters should be encoded as dictionary".into(),
+            ));
+        }
+        let parameters = decode_parameters(dict)?;
         unsafe {
-            c4query_setParameters(self.inner.as_ptr(), param_string.as_fl_slice());
+            c4query_setParameters(self.inner.as_ptr(), params.as_fl_slice());
         }
+        *self.parameters.borrow_mut() = parameters;
         Ok(())
     }
 
+    /// Number of columns in query's result
+    #[inline]
+    pub fn column_count(&self) -> u32 {
+        unsafe { c4query_columnCount(self.inner.as_ptr()) }
+    }
+
+    /// Title of column `i`, alias from `AS`, property name or `$N`
+    /// for unnamed expressions
+    pub fn column_title(&self, i: u32) -> Result<&str> {
+        let n = self.column_count();
+        if i >= n {
+            return Err(Error::LogicError(format!(
+                "Query::column_title: Index out of bounds {i} / {n}"
+            )));
+        }
+        unsafe { c4query_columnTitle(self.inner.as_ptr(), i) }
+            .try_into()
+            .map_err(|_| Error::InvalidUtf8)
+    }
+
     pub fn run(&self) -> Result<Enumerator> {
         let mut c4err = c4error_init();
         let it = unsafe {
@@ -90,6 +185,206 @@ impl Query<'_> {
             })
             .ok_or_else(|| c4err.into())
     }
+
+    /// Run query and collect all rows into `OwnedRow`s,
+    /// so results are not tied to lifetime of `Query`
+    pub fn run_to_owned_rows(&self) -> Result<Vec<OwnedRow>> {
+        let mut rows = Vec::new();
+        let mut it = self.run()?;
+        while let Some(item) = it.next()? {
+            rows.push(item.to_owned_row()?);
+        }
+        Ok(rows)
+    }
+
+    /// Run query without blocking other tasks of tokio's runtime.
+    ///
+    /// `Query` borrows `Database` and `Database` is `Send`, but not `Sync`,
+    /// so query can not be moved to `tokio::task::spawn_blocking`'s pool.
+    /// Also blocking task can outlive the future if it was dropped,
+    /// and `&Database` would be used from two threads at the same time.
+    /// Instead `tokio::task::block_in_place` is used, so this function
+    /// should be called from multi-threaded tokio runtime,
+    /// in other cases (`current_thread` runtime or outside of tokio's runtime)
+    /// it returns error.
+    #[cfg(feature = "async-query")]
+    pub async fn run_async(&self) -> Result<Vec<OwnedRow>> {
+        use tokio::runtime::{Handle, RuntimeFlavor};
+        match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Ok(RuntimeFlavor::MultiThread) => {
+                tokio::task::block_in_place(|| self.run_to_owned_rows())
+            }
+            _ => Err(Error::LogicError(
+                "Query::run_async requires multi-threaded tokio runtime".into(),
+            )),
+        }
+    }
+
+    /// Start observing query ("live query"). The query will be re-run in background
+    /// after the database changes, `callback_f` will be called when results changed.
+    /// Use `Query::current_results` to get new results.
+    /// Calling this method again replaces previous observer.
+    pub fn observe<F>(&mut self, callback_f: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.observer.take();
+        let obs = QueryObserver::new(self, callback_f)?;
+        unsafe { c4queryobs_setEnabled(obs.inner.as_ptr(), true) };
+        self.observer = Some(obs);
+        Ok(())
+    }
+
+    /// Returns the latest results of observed query,
+    /// or `None` if results not changed since last call
+    pub fn current_results(&self) -> Result<Option<Enumerator>> {
+        let obs = self.observer.as_ref().ok_or_else(|| {
+            Error::LogicError("Query::current_results: query is not observed".into())
+        })?;
+        let mut c4err = c4error_init();
+        let it = unsafe { c4queryobs_getEnumerator(obs.inner.as_ptr(), true, &mut c4err) };
+        match NonNull::new(it) {
+            Some(inner) => Ok(Some(Enumerator {
+                _query: self,
+                reach_end: false,
+                inner,
+            })),
+            None if c4err.code == 0 => Ok(None),
+            None => Err(c4err.into()),
+        }
+    }
+}
+
+fn encode_value(value: FLValue) -> Result<FLSliceResult> {
+    unsafe {
+        let enc = FLEncoder_New();
+        let all_ok = FLEncoder_WriteValue(enc, value);
+        let mut fl_err = FLError::kFLNoError;
+        let data = FLEncoder_Finish(enc, &mut fl_err);
+        FLEncoder_Free(enc);
+        if !data.is_empty() && all_ok {
+            Ok(data)
+        } else {
+            Err(serde_fleece::Error::Fleece(fl_err).into())
+        }
+    }
+}
+
+fn decode_parameters(dict: FLDict) -> Result<BTreeMap<String, FLSliceResult>> {
+    let mut ret = BTreeMap::new();
+    let mut it = MaybeUninit::<FLDictIterator>::uninit();
+    let mut it = unsafe {
+        FLDictIterator_Begin(dict, it.as_mut_ptr());
+        it.assume_init()
+    };
+    let mut res = Ok(());
+    while !unsafe { FLDictIterator_GetValue(&it) }.is_null() {
+        let key: &str = match unsafe { FLDictIterator_GetKeyString(&it) }.try_into() {
+            Ok(key) => key,
+            Err(_) => {
+                res = Err(Error::InvalidUtf8);
+                break;
+            }
+        };
+        match encode_value(unsafe { FLDictIterator_GetValue(&it) }) {
+            Ok(value) => {
+                ret.insert(key.to_string(), value);
+            }
+            Err(err) => {
+                res = Err(err);
+                break;
+            }
+        }
+        if !unsafe { FLDictIterator_Next(&mut it) } {
+            break;
+        }
+    }
+    unsafe { FLDictIterator_End(&mut it) };
+    res.map(|_| ret)
+}
+
+fn encode_parameters(parameters: &BTreeMap<String, FLSliceResult>) -> Result<FLSliceResult> {
+    unsafe {
+        let enc = FLEncoder_New();
+        let mut all_ok = FLEncoder_BeginDict(enc, parameters.len());
+        for (key, value) in parameters {
+            all_ok &= FLEncoder_WriteKey(enc, key.into());
+            all_ok &= FLEncoder_WriteValue(
+                enc,
+                FLValue_FromData(value.as_fl_slice(), FLTrust::kFLTrusted),
+            );
+        }
+        all_ok &= FLEncoder_EndDict(enc);
+        let mut fl_err = FLError::kFLNoError;
+        let data = FLEncoder_Finish(enc, &mut fl_err);
+        FLEncoder_Free(enc);
+        if !data.is_empty() && all_ok {
+            Ok(data)
+        } else {
+            Err(serde_fleece::Error::Fleece(fl_err).into())
+        }
+    }
+}
+
+struct QueryObserver {
+    inner: NonNull<C4QueryObserver>,
+    free_callback_f: unsafe extern "C" fn(_: *mut c_void),
+    boxed_callback_f: NonNull<c_void>,
+}
+
+impl Drop for QueryObserver {
+    fn drop(&mut self) {
+        unsafe {
+            c4queryobs_free(self.inner.as_ptr());
+            (self.free_callback_f)(self.boxed_callback_f.as_ptr());
+        }
+    }
+}
+
+impl QueryObserver {
+    fn new<F>(query: &Query, callback_f: F) -> Result<QueryObserver>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            _obs: *mut C4QueryObserver,
+            _query: *mut C4Query,
+            context: *mut c_void,
+        ) where
+            F: FnMut() + Send,
+        {
+            let r = catch_unwind(|| {
+                let boxed_f = context as *mut F;
+                assert!(
+                    !boxed_f.is_null(),
+                    "QueryObserver: Internal error - null function pointer"
+                );
+                (*boxed_f)();
+            });
+            if r.is_err() {
+                error!("QueryObserver::call_boxed_closure catch panic aborting");
+                abort();
+            }
+        }
+        let boxed_f: *mut F = Box::into_raw(Box::new(callback_f));
+        let obs = unsafe {
+            c4queryobs_create(
+                query.inner.as_ptr(),
+                Some(call_boxed_closure::<F>),
+                boxed_f as *mut c_void,
+            )
+        };
+        NonNull::new(obs)
+            .map(|inner| QueryObserver {
+                inner,
+                free_callback_f: free_boxed_value::<F>,
+                boxed_callback_f: unsafe { NonNull::new_unchecked(boxed_f as *mut c_void) },
+            })
+            .ok_or_else(|| {
+                unsafe { free_boxed_value::<F>(boxed_f as *mut c_void) };
+                Error::LogicError("c4queryobs_create return null".into())
+            })
+    }
 }
 
 pub struct Enumerator<'query> {
@@ -164,11 +459,234 @@ impl<'a> Enumerator<'a> {
         FromValueRef::column_result(value_ref)
     }
 
+    /// Deserialize column `i`, `T` can borrow from row (`&str`, `&[u8]`),
+    /// such borrow is tied to the current item, so it can not outlive
+    /// the row, the enumerator or advancing to the next row:
+    ///
+    /// ```compile_fail
+    /// use couchbase_lite::{Database, fallible_streaming_iterator::FallibleStreamingIterator};
+    ///
+    /// fn first_s(db: &Database) -> Result<(), couchbase_lite::Error> {
+    ///     let query = db.n1ql_query("SELECT s FROM _default")?;
+    ///     let s: &str = {
+    ///         let mut iter = query.run()?;
+    ///         let item = iter.next()?.unwrap();
+    ///         item.get_checked_serde(0)?
+    ///     };
+    ///     println!("{s}");
+    ///     Ok(())
+    /// }
+    /// ```
+    /// Deserialize into owned type (`String`, `Vec<u8>`) to keep value longer.
     #[inline]
     pub fn get_checked_serde<'de, T: serde::de::Deserialize<'de>>(&'de self, i: u32) -> Result<T> {
         let value = self.do_get_raw_checked(i)?;
         let value = NonNullConst::new(value).ok_or_else(|| {
-            Error::LogicError(format!("Query parameter {i} is null, can not deserialize"))
+            Error::LogicError(format!("Query column {i} is null, can not deserialize"))
+        })?;
+        serde_fleece::from_fl_value(value).map_err(Error::from)
+    }
+
+    /// Convert enumerator into `Stream` of rows.
+    /// This is synchronous adapter: the next row is fetched right inside `poll_next`
+    /// on the current thread, there is no blocking task behind it, because
+    /// enumerator borrows `Database` and can not be moved to other thread.
+    /// Query is already executed by `Query::run`, and rows are enumerated from
+    /// in-memory results, so each poll is short, but still it is blocking call.
+    #[cfg(feature = "async-query")]
+    pub fn into_sync_stream(mut self) -> impl futures_util::Stream<Item = Result<OwnedRow>> + 'a {
+        let mut done = false;
+        futures_util::stream::poll_fn(move |_cx| {
+            if done {
+                return std::task::Poll::Ready(None);
+            }
+            let item = match self.next() {
+                Ok(Some(row)) => Some(row.to_owned_row()),
+                Ok(None) => None,
+                Err(err) => Some(Err(err)),
+            };
+            done = !matches!(item, Some(Ok(_)));
+            std::task::Poll::Ready(item)
+        })
+    }
+
+    /// Current row with access to columns by title, like `row["s"].as_str()?`
+    pub fn as_row(&self) -> Row<'_> {
+        let n = unsafe { FLArrayIterator_GetCount(&self.inner.as_ref().columns) };
+        let values = (0..n)
+            .map(|i| unsafe {
+                ValueRef::new(FLArrayIterator_GetValueAt(&self.inner.as_ref().columns, i))
+            })
+            .collect();
+        Row {
+            query: self._query,
+            values,
+        }
+    }
+
+    /// Copy all columns of the current row into `OwnedRow`
+    pub fn to_owned_row(&self) -> Result<OwnedRow> {
+        let columns = unsafe { &self.inner.as_ref().columns };
+        let n = unsafe { FLArrayIterator_GetCount(columns) };
+        unsafe {
+            let enc = FLEncoder_New();
+            let mut all_ok = FLEncoder_BeginArray(enc, n as usize);
+            for i in 0..n {
+                all_ok &= FLEncoder_WriteValue(enc, FLArrayIterator_GetValueAt(columns, i));
+            }
+            all_ok &= FLEncoder_EndArray(enc);
+            let mut fl_err = FLError::kFLNoError;
+            let data = FLEncoder_Finish(enc, &mut fl_err);
+            FLEncoder_Free(enc);
+            if !data.is_empty() && all_ok {
+                Ok(OwnedRow { data })
+            } else {
+                Err(serde_fleece::Error::Fleece(fl_err).into())
+            }
+        }
+    }
+
+    /// Deserialize all columns of the current row at once,
+    /// columns are treated as sequence, so `T` can be tuple or struct
+    /// with fields in the same order as columns in query.
+    /// Row is copied before decoding, so `T` can not borrow from it.
+    #[inline]
+    pub fn get_row<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        self.to_owned_row()?.get_row()
+    }
+
+    /// Full-text matches of the current row, if query uses full-text search
+    pub fn full_text_matches(&self) -> &[C4FullTextMatch] {
+        let (matches, count) = unsafe {
+            let inner = self.inner.as_ref();
+            (inner.fullTextMatches, inner.fullTextMatchCount)
+        };
+        if matches.is_null() || count == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(matches, count as usize) }
+        }
+    }
+
+    /// Given a full-text match, returns the entire text that was matched,
+    /// `start` and `length` of `C4FullTextMatch` are byte offsets in this text
+    pub fn full_text_matched(&self, term: &C4FullTextMatch) -> Result<String> {
+        let mut c4err = c4error_init();
+        let text = unsafe { c4query_fullTextMatched(self._query.inner.as_ptr(), term, &mut c4err) };
+        if text.buf.is_null() {
+            return Err(c4err.into());
+        }
+        let text: &str = text
+            .as_fl_slice()
+            .try_into()
+            .map_err(|_| Error::InvalidUtf8)?;
+        Ok(text.to_string())
+    }
+}
+
+/// Row of query result with access to columns by title,
+/// missing column is returned as `ValueRef::Null`
+pub struct Row<'a> {
+    query: &'a Query<'a>,
+    values: Vec<ValueRef<'a>>,
+}
+
+impl<'a> Row<'a> {
+    /// Value of column with title `title`
+    pub fn get(&self, title: &str) -> Option<&ValueRef<'a>> {
+        let idx = (0..self.values.len())
+            .find(|i| matches!(self.query.column_title(*i as u32), Ok(x) if x == title))?;
+        self.values.get(idx)
+    }
+    /// Number of columns
+    #[inline]
+    pub fn column_count(&self) -> usize {
+        self.values.len()
+    }
+    /// Columns values in order of query
+    #[inline]
+    pub fn values(&self) -> &[ValueRef<'a>] {
+        &self.values
+    }
+}
+
+impl<'a> Index<&str> for Row<'a> {
+    type Output = ValueRef<'a>;
+
+    #[inline]
+    fn index(&self, title: &str) -> &ValueRef<'a> {
+        self.get(title).unwrap_or(&ValueRef::Null)
+    }
+}
+
+/// Query's result row that owns its data, so it is not bound to
+/// lifetime of `Enumerator` and can be sent to another thread
+pub struct OwnedRow {
+    data: FLSliceResult,
+}
+
+/// `data` is immutable buffer with reference counter,
+/// owned by this struct
+unsafe impl Send for OwnedRow {}
+
+impl OwnedRow {
+    fn get_value(&self, i: u32) -> Result<FLValue> {
+        let columns = unsafe {
+            FLValue_AsArray(FLValue_FromData(
+                self.data.as_fl_slice(),
+                FLTrust::kFLTrusted,
+            ))
+        };
+        let n = unsafe { FLArray_Count(columns) };
+        if i >= n {
+            return Err(Error::LogicError(format!(
+                "OwnedRow::get_raw_checked: Index out of bounds {} / {}",
+                i, n
+            )));
+        }
+        Ok(unsafe { FLArray_Get(columns, i) })
+    }
+
+    /// Number of columns
+    pub fn column_count(&self) -> u32 {
+        unsafe {
+            FLArray_Count(FLValue_AsArray(FLValue_FromData(
+                self.data.as_fl_slice(),
+                FLTrust::kFLTrusted,
+            )))
+        }
+    }
+
+    #[inline]
+    pub fn get_raw_checked(&self, i: u32) -> Result<ValueRef<'_>> {
+        let value = self.get_value(i)?;
+        Ok(unsafe { ValueRef::new(value) })
+    }
+
+    #[inline]
+    pub fn get_checked<'a, T>(&'a self, i: u32) -> Result<T>
+    where
+        T: FromValueRef<'a>,
+    {
+        let value_ref = self.get_raw_checked(i)?;
+        FromValueRef::column_result(value_ref)
+    }
+
+    /// Deserialize all columns of row as sequence into tuple or struct
+    pub fn get_row<'de, T: serde::de::Deserialize<'de>>(&'de self) -> Result<T> {
+        let value = unsafe { FLValue_FromData(self.data.as_fl_slice(), FLTrust::kFLTrusted) };
+        let value = NonNullConst::new(value)
+            .ok_or_else(|| Error::LogicError("OwnedRow: data is not valid fleece".into()))?;
+        serde_fleece::from_fl_value(value).map_err(Error::from)
+    }
+
+    /// Deserialize column `i`, borrowed `T` (`&str`, `&[u8]`) lives
+    /// as long as `OwnedRow`
+    #[inline]
+    pub fn get_checked_serde<'de, T: serde::de::Deserialize<'de>>(&'de self, i: u32) -> Result<T> {
+        let value = self.get_value(i)?;
+        let value = NonNullConst::new(value).ok_or_else(|| {
+            Error::LogicError(format!("Query column {i} is null, can not deserialize"))
         })?;
         serde_fleece::from_fl_value(value).map_err(Error::from)
     }
@@ -1,20 +1,33 @@
+mod socket_factory;
+#[cfg(feature = "use-std-websocket")]
+mod std_socket;
 #[cfg(feature = "use-tokio-websocket")]
 mod tokio_socket;
 
 use crate::{
     error::{c4error_init, Error, Result},
     ffi::{
-        c4address_fromURL, c4repl_free, c4repl_getStatus, c4repl_new, c4repl_retry, c4repl_start,
-        c4repl_stop, kC4DefaultCollectionSpec, C4Address, C4CollectionSpec, C4DocumentEnded,
-        C4Progress, C4ReplicationCollection, C4Replicator, C4ReplicatorActivityLevel,
-        C4ReplicatorDocumentsEndedCallback, C4ReplicatorMode, C4ReplicatorParameters,
-        C4ReplicatorStatus, C4ReplicatorStatusChangedCallback, C4ReplicatorValidationFunction,
-        C4RevisionFlags, C4String, FLDict, FLSliceResult,
+        c4address_fromURL, c4base_release, c4base_retain, c4repl_free, c4repl_getResponseHeaders,
+        c4repl_getStatus, c4repl_new, c4repl_retry, c4repl_setHostReachable, c4repl_setSuspended,
+        c4repl_start, c4repl_stop, kC4DefaultCollectionSpec, C4Address, C4CollectionSpec,
+        C4DocumentEnded, C4Progress, C4ReplicationCollection, C4Replicator,
+        C4ReplicatorActivityLevel, C4ReplicatorDocumentsEndedCallback, C4ReplicatorMode,
+        C4ReplicatorParameters, C4ReplicatorStatus, C4ReplicatorStatusChangedCallback,
+        C4ReplicatorStatusFlags, C4ReplicatorValidationFunction, C4RevisionFlags, C4String, FLDict,
+        FLDictIterator, FLDictIterator_Begin, FLDictIterator_End, FLDictIterator_GetKeyString,
+        FLDictIterator_GetValue, FLDictIterator_Next, FLDict_Count, FLDict_Get, FLSliceResult,
+        FLTrust, FLValue_AsDict, FLValue_FromData,
     },
+    value::ValueRef,
     Database,
 };
 use log::{error, info, trace};
+use serde::Deserialize;
 use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    marker::PhantomData,
     mem::{self, MaybeUninit},
     os::raw::c_void,
     panic::catch_unwind,
@@ -22,7 +35,12 @@ use std::{
     ptr,
     ptr::NonNull,
     slice, str,
-    sync::Once,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, Once,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 /// Replicator of database
@@ -32,8 +50,17 @@ pub struct Replicator {
     c_callback_on_status_changed: C4ReplicatorStatusChangedCallback,
     c_callback_on_documents_ended: C4ReplicatorDocumentsEndedCallback,
     free_callback_f: unsafe fn(_: *mut c_void),
-    boxed_callback_f: NonNull<c_void>,
+    /// `None` only after callbacks were moved to recreated replicator
+    boxed_callback_f: Option<NonNull<c_void>>,
     mode: ReplicatorMode,
+    extra_headers: Vec<String>,
+    close_timeout: Duration,
+    reset_checkpoint: bool,
+    auth: ReplicatorAuthentication,
+    urls: Vec<String>,
+    url_idx: usize,
+    doc_counters: Arc<DocumentCounters>,
+    auto_retry: Option<RetryTask>,
 }
 
 /// Parameters describing a replication, used when creating `Replicator`
@@ -43,8 +70,15 @@ pub struct ReplicatorParameters<StateCallback, DocumentsEndedCallback, Validatio
     documents_ended_callback: DocumentsEndedCallback,
     auth: ReplicatorAuthentication,
     mode: ReplicatorMode,
+    extra_headers: Vec<String>,
+    close_timeout: Duration,
+    auto_retry: Option<BackoffConfig>,
 }
 
+/// How long socket waits for acknowledgement of close from server,
+/// acording to comment from c4SocketTypes.h
+pub(crate) const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[derive(Clone, Copy)]
 struct ReplicatorMode {
     push: C4ReplicatorMode,
@@ -73,8 +107,38 @@ impl<SC, DEC, V> ReplicatorParameters<SC, DEC, V> {
             documents_ended_callback: self.documents_ended_callback,
             auth: self.auth,
             mode: self.mode,
+            extra_headers: self.extra_headers,
+            close_timeout: self.close_timeout,
+            auto_retry: self.auto_retry,
         }
     }
+    /// Set filter that can reject incoming revisions, like `with_validation_func`,
+    /// but revision is passed as safe wrapper `ReplicatedDoc`
+    #[inline]
+    pub fn with_replicated_doc_filter<F>(
+        self,
+        mut filter: F,
+    ) -> ReplicatorParameters<SC, DEC, impl ReplicatorValidationFunction>
+    where
+        F: FnMut(&ReplicatedDoc) -> bool + Send + 'static,
+    {
+        self.with_validation_func(
+            move |coll_spec: C4CollectionSpec,
+                  doc_id: C4String,
+                  rev_id: C4String,
+                  flags: C4RevisionFlags,
+                  body: FLDict| {
+                filter(&ReplicatedDoc {
+                    coll_spec,
+                    doc_id,
+                    rev_id,
+                    flags,
+                    body,
+                    marker: PhantomData,
+                })
+            },
+        )
+    }
     /// Set callback to reports back change of replicator state
     #[inline]
     pub fn with_state_changed_callback<StateCallback>(
@@ -90,6 +154,9 @@ impl<SC, DEC, V> ReplicatorParameters<SC, DEC, V> {
             documents_ended_callback: self.documents_ended_callback,
             auth: self.auth,
             mode: self.mode,
+            extra_headers: self.extra_headers,
+            close_timeout: self.close_timeout,
+            auto_retry: self.auto_retry,
         }
     }
     /// Set callback to reports about the replication status of documents
@@ -107,6 +174,48 @@ impl<SC, DEC, V> ReplicatorParameters<SC, DEC, V> {
             documents_ended_callback,
             auth: self.auth,
             mode: self.mode,
+            extra_headers: self.extra_headers,
+            close_timeout: self.close_timeout,
+            auto_retry: self.auto_retry,
+        }
+    }
+    /// Set additional HTTP headers (name, value) that will be sent
+    /// with request to remote server, for example `User-Agent` or `X-Api-Key`
+    pub fn with_extra_headers(self, headers: impl IntoIterator<Item = (String, String)>) -> Self {
+        let extra_headers = headers
+            .into_iter()
+            .map(|(name, value)| format!("{name}:{value}"))
+            .collect();
+        Self {
+            extra_headers,
+            ..self
+        }
+    }
+    /// Set how long to wait for server's acknowledgement of
+    /// websocket close before disconnect, default is 5 seconds.
+    /// Honoured by tokio and std websocket implementations, builtin
+    /// couchbase-lite websocket and custom socket factories ignore it
+    #[inline]
+    pub fn with_close_timeout(self, close_timeout: Duration) -> Self {
+        Self {
+            close_timeout,
+            ..self
+        }
+    }
+    /// Reconnect automatically when replicator goes offline,
+    /// delay between attempts is chosen according to `backoff`.
+    /// Reconnection is scheduled on one separate thread per replicator,
+    /// so state changed callback is not blocked. Nothing is scheduled while
+    /// replicator is suspended or host is unreachable, scheduled attempt is canceled
+    /// by `Replicator::stop` and is skipped if replicator is not offline at that time
+    /// (for example couchbase-lite-core reconnected by itself).
+    /// `c4repl_retry` cancels core's own pending retry, so there is only one
+    /// reconnection at a time.
+    #[inline]
+    pub fn with_auto_retry(self, backoff: BackoffConfig) -> Self {
+        Self {
+            auto_retry: Some(backoff),
+            ..self
         }
     }
     /// Set push mode (from db to remote/other db)
@@ -136,13 +245,13 @@ impl<SC, DEC, V> ReplicatorParameters<SC, DEC, V> {
 impl Default
     for ReplicatorParameters<
         fn(ReplicatorState),
-        fn(bool, &mut dyn Iterator<Item = &C4DocumentEnded>),
-        fn(C4String, C4String, C4String, C4RevisionFlags, FLDict) -> bool,
+        fn(bool, &mut dyn Iterator<Item = DocumentEnded>),
+        fn(C4CollectionSpec, C4String, C4String, C4RevisionFlags, FLDict) -> bool,
     >
 {
     fn default() -> Self {
         Self {
-            validation_cb: |_coll_name, _doc_id, _rev_id, _rev_flags, _body| true,
+            validation_cb: |_coll_spec, _doc_id, _rev_id, _rev_flags, _body| true,
             state_changed_callback: |_repl_state| {},
             documents_ended_callback: |_pushing, _doc_iter| {},
             auth: ReplicatorAuthentication::None,
@@ -150,6 +259,223 @@ impl Default
                 push: C4ReplicatorMode::kC4Continuous,
                 pull: C4ReplicatorMode::kC4Continuous,
             },
+            extra_headers: Vec::new(),
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+            auto_retry: None,
+        }
+    }
+}
+
+/// Parameters of exponential backoff used for automatic reconnection,
+/// see `ReplicatorParameters::with_auto_retry`
+#[derive(Debug, Clone, Copy)]
+pub struct BackoffConfig {
+    /// Delay before the first attempt
+    pub initial_delay: Duration,
+    /// Upper limit of delay between attempts
+    pub max_delay: Duration,
+    /// Delay multiplier applied after each failed attempt
+    pub multiplier: f64,
+    /// Maximum number of attempts in a row, `None` means retry forever.
+    /// Counter is reset when replicator becomes idle or busy again
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5 * 60),
+            multiplier: 2.,
+            max_attempts: None,
+        }
+    }
+}
+
+impl BackoffConfig {
+    /// Delay before attempt number `attempt` (starting from 0),
+    /// random jitter picks value between half and full exponential delay
+    fn delay(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.powi(attempt.min(i32::MAX as u32) as i32);
+        let delay = self.initial_delay.as_secs_f64() * exp;
+        let delay = delay.min(self.max_delay.as_secs_f64());
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(attempt);
+        let jitter = (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64;
+        Duration::from_secs_f64(delay * (0.5 + 0.5 * jitter))
+    }
+}
+
+/// State of automatic reconnection shared between status changed callback
+/// and retry thread, see `ReplicatorParameters::with_auto_retry`
+struct AutoRetry {
+    backoff: BackoffConfig,
+    state: Mutex<AutoRetryState>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct AutoRetryState {
+    attempts: u32,
+    /// When `c4repl_retry` should be called, `None` if nothing is scheduled
+    deadline: Option<Instant>,
+    shutdown: bool,
+}
+
+/// `C4Replicator` retained by us, so it can be used from retry thread
+struct RetainedReplicator(NonNull<C4Replicator>);
+
+unsafe impl Send for RetainedReplicator {}
+
+impl Drop for RetainedReplicator {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { c4base_release(self.0.as_ptr() as *mut c_void) };
+    }
+}
+
+impl AutoRetry {
+    fn new(backoff: BackoffConfig) -> Self {
+        Self {
+            backoff,
+            state: Mutex::new(AutoRetryState::default()),
+            cond: Condvar::new(),
+        }
+    }
+
+    #[inline]
+    fn lock(&self) -> MutexGuard<AutoRetryState> {
+        self.state.lock().expect("auto retry lock failed")
+    }
+
+    fn on_status_changed(&self, status: &C4ReplicatorStatus) {
+        let mut state = self.lock();
+        if state.shutdown {
+            return;
+        }
+        match status.level {
+            C4ReplicatorActivityLevel::kC4Offline => {
+                if !can_retry(status.flags) {
+                    trace!(
+                        "auto retry: suspended or host unreachable, flags {:?}",
+                        status.flags
+                    );
+                    state.deadline = None;
+                    return;
+                }
+                if state.deadline.is_some() {
+                    return;
+                }
+                if let Some(max_attempts) = self.backoff.max_attempts {
+                    if state.attempts >= max_attempts {
+                        info!("auto retry: give up after {} attempts", state.attempts);
+                        return;
+                    }
+                }
+                let delay = self.backoff.delay(state.attempts);
+                state.attempts += 1;
+                info!("auto retry: attempt {} in {delay:?}", state.attempts);
+                state.deadline = Some(Instant::now() + delay);
+                self.cond.notify_one();
+            }
+            C4ReplicatorActivityLevel::kC4Idle | C4ReplicatorActivityLevel::kC4Busy => {
+                state.attempts = 0;
+                state.deadline = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Cancel scheduled attempt, if any
+    fn cancel(&self) {
+        self.lock().deadline = None;
+        self.cond.notify_one();
+    }
+
+    fn run(&self, repl: RetainedReplicator) {
+        let mut state = self.lock();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            let Some(deadline) = state.deadline else {
+                state = self.cond.wait(state).expect("condvar wait failed");
+                continue;
+            };
+            let now = Instant::now();
+            if now < deadline {
+                state = self
+                    .cond
+                    .wait_timeout(state, deadline - now)
+                    .expect("condvar wait failed")
+                    .0;
+                continue;
+            }
+            state.deadline = None;
+            // status changed callback can be called from c4repl_retry
+            drop(state);
+            let status = unsafe { c4repl_getStatus(repl.0.as_ptr()) };
+            if status.level == C4ReplicatorActivityLevel::kC4Offline && can_retry(status.flags) {
+                let mut c4err = c4error_init();
+                let will_reconnect = unsafe { c4repl_retry(repl.0.as_ptr(), &mut c4err) };
+                trace!("auto retry: will reconnect {will_reconnect}, err {c4err:?}");
+            } else {
+                trace!("auto retry: skip, status {status:?}");
+            }
+            state = self.lock();
+        }
+    }
+}
+
+#[inline]
+fn can_retry(flags: C4ReplicatorStatusFlags) -> bool {
+    (flags & C4ReplicatorStatusFlags::kC4Suspended).0 == 0
+        && (flags & C4ReplicatorStatusFlags::kC4HostReachable).0 != 0
+}
+
+/// Retry thread bound to one `C4Replicator`, thread is stopped on drop
+struct RetryTask {
+    control: Arc<AutoRetry>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl RetryTask {
+    fn spawn(control: Arc<AutoRetry>, repl: NonNull<C4Replicator>) -> Self {
+        {
+            let mut state = control.lock();
+            state.shutdown = false;
+            state.deadline = None;
+        }
+        let repl = RetainedReplicator(unsafe {
+            NonNull::new_unchecked(c4base_retain(repl.as_ptr() as *mut c_void) as *mut C4Replicator)
+        });
+        let thread_control = control.clone();
+        let thread = thread::Builder::new()
+            .name("cbl-repl-retry".into())
+            .spawn(move || thread_control.run(repl))
+            .map_err(|err| error!("auto retry: can not spawn thread: {err}"))
+            .ok();
+        Self { control, thread }
+    }
+
+    /// Stop thread, shared state can be reused for the new `C4Replicator`
+    fn stop(self) -> Arc<AutoRetry> {
+        self.control.clone()
+    }
+}
+
+impl Drop for RetryTask {
+    fn drop(&mut self) {
+        {
+            let mut state = self.control.lock();
+            state.shutdown = true;
+            state.deadline = None;
+        }
+        self.control.cond.notify_one();
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("auto retry: thread panicked");
+            }
         }
     }
 }
@@ -162,6 +488,26 @@ struct CallbackContext<
     validation_cb: ValidationCb,
     state_cb: StateCb,
     docs_ended_cb: DocumentsEndedCb,
+    auto_retry: Option<Arc<AutoRetry>>,
+    doc_counters: Arc<DocumentCounters>,
+}
+
+/// Totals of documents ended callback, survive `Replicator::restart`
+#[derive(Default)]
+struct DocumentCounters {
+    errors: AtomicU64,
+    conflicts: AtomicU64,
+}
+
+impl DocumentCounters {
+    fn update(&self, pushing: bool, doc: &DocumentEnded) {
+        if doc.error().is_some() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if !pushing && doc.is_conflict() {
+            self.conflicts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -179,9 +525,13 @@ impl Drop for Replicator {
     #[inline]
     fn drop(&mut self) {
         trace!("repl drop {:?}", self.inner.as_ptr());
+        // retry thread uses callbacks context, so stop it first
+        drop(self.auto_retry.take());
         unsafe {
             c4repl_free(self.inner.as_ptr());
-            (self.free_callback_f)(self.boxed_callback_f.as_ptr());
+            if let Some(boxed_callback_f) = self.boxed_callback_f {
+                (self.free_callback_f)(boxed_callback_f.as_ptr());
+            }
         }
     }
 }
@@ -200,13 +550,14 @@ define_trait_alias!(
 );
 define_trait_alias!(
     ReplicatorDocumentsEndedCallback,
-    FnMut(bool, &mut dyn Iterator<Item = &C4DocumentEnded>) + Send + 'static
+    FnMut(bool, &mut dyn Iterator<Item = DocumentEnded>) + Send + 'static
 );
 
 impl Replicator {
     /// # Arguments
     /// * `url` - should be something like "ws://192.168.1.132:4984/demo/"
     /// * `params` - parameters of replicator
+    #[inline]
     pub fn new<StateCallback, DocumentsEndedCallback, ValidationF>(
         db: &Database,
         url: &str,
@@ -217,6 +568,34 @@ impl Replicator {
         StateCallback: ReplicatorStatusChangedCallback,
         DocumentsEndedCallback: ReplicatorDocumentsEndedCallback,
     {
+        Replicator::new_with_urls(db, &[url], params)
+    }
+
+    /// Create replicator for several URLs of the same remote database,
+    /// the first one is used at start, `Replicator::failover` switches to the next one.
+    /// Checkpoint is shared between URLs, so switching doesn't restart replication
+    /// from the beginning.
+    ///
+    /// If there are several URLs, the first one is used as unique ID of remote database,
+    /// so checkpoint is bound to it. Replicator with one URL (`Replicator::new`)
+    /// has checkpoint bound to its URL as before, so switching the same database
+    /// between one and several URLs, or changing the first URL in the list,
+    /// resets checkpoint and replication starts from the beginning
+    pub fn new_with_urls<StateCallback, DocumentsEndedCallback, ValidationF>(
+        db: &Database,
+        urls: &[&str],
+        params: ReplicatorParameters<StateCallback, DocumentsEndedCallback, ValidationF>,
+    ) -> Result<Self>
+    where
+        ValidationF: ReplicatorValidationFunction,
+        StateCallback: ReplicatorStatusChangedCallback,
+        DocumentsEndedCallback: ReplicatorDocumentsEndedCallback,
+    {
+        if urls.is_empty() {
+            return Err(Error::LogicError(
+                "Replicator::new_with_urls: empty list of URLs".into(),
+            ));
+        }
         unsafe extern "C" fn call_validation<F, F2, F3>(
             coll_spec: C4CollectionSpec,
             doc_id: C4String,
@@ -266,6 +645,9 @@ impl Replicator {
                         error!("replicator status change: invalid status {err}");
                     }
                 }
+                if let Some(auto_retry) = (*ctx).auto_retry.as_ref() {
+                    auto_retry.on_status_changed(&status);
+                }
             });
             if r.is_err() {
                 error!("Replicator::call_on_status_changed: catch panic aborting");
@@ -297,7 +679,10 @@ impl Replicator {
                     "Replicator::call_on_documents_ended: Internal error - null function pointer"
                 );
                 let docs: &[*const C4DocumentEnded] = slice::from_raw_parts(docs, num_docs);
-                let mut it = docs.iter().map(|x| &**x);
+                for doc in docs.iter().map(|x| DocumentEnded::from(&**x)) {
+                    (*ctx).doc_counters.update(pushing, &doc);
+                }
+                let mut it = docs.iter().map(|x| DocumentEnded::from(&**x));
                 ((*ctx).docs_ended_cb)(pushing, &mut it);
             });
             if r.is_err() {
@@ -306,16 +691,27 @@ impl Replicator {
             }
         }
 
+        let doc_counters = Arc::new(DocumentCounters::default());
+        let auto_retry = params
+            .auto_retry
+            .map(|backoff| Arc::new(AutoRetry::new(backoff)));
         let ctx = Box::new(CallbackContext {
             validation_cb: params.validation_cb,
             state_cb: params.state_changed_callback,
             docs_ended_cb: params.documents_ended_callback,
+            auto_retry: auto_retry.clone(),
+            doc_counters: doc_counters.clone(),
         });
         let ctx_p = Box::into_raw(ctx);
         Replicator::do_new(
             db,
-            url,
-            &params.auth,
+            urls.iter().map(|x| x.to_string()).collect(),
+            0,
+            params.auth,
+            params.extra_headers,
+            params.close_timeout,
+            doc_counters,
+            auto_retry,
             free_boxed_value::<CallbackContext<ValidationF, StateCallback, DocumentsEndedCallback>>,
             unsafe { NonNull::new_unchecked(ctx_p as *mut c_void) },
             Some(call_validation::<ValidationF, StateCallback, DocumentsEndedCallback>),
@@ -329,6 +725,7 @@ impl Replicator {
     /// * `reset` - If true, the replicator will reset its checkpoint
     ///             and start replication from the beginning.
     pub fn start(&mut self, reset: bool) -> Result<()> {
+        let reset = reset || mem::take(&mut self.reset_checkpoint);
         unsafe { c4repl_start(self.inner.as_ptr(), reset) };
         let status: ReplicatorState = self.status().into();
         if let ReplicatorState::Stopped(err) = status {
@@ -338,12 +735,21 @@ impl Replicator {
         }
     }
 
+    /// Reset replicator's checkpoint on the next `Replicator::start`,
+    /// so replication will start from the beginning (full re-sync).
+    /// To apply it to running replicator call `stop` and then `start`
+    #[inline]
+    pub fn reset_checkpoint(&mut self) {
+        self.reset_checkpoint = true;
+    }
+
     /// Full recreation of database replicator except callbacks,
     ///
     /// * `url`   - new url
     /// * `auth`  - new auth information
     /// * `reset` - If true, the replicator will reset its checkpoint
     ///             and start replication from the beginning.
+    #[inline]
     pub fn restart(
         self,
         db: &Database,
@@ -351,29 +757,82 @@ impl Replicator {
         auth: &ReplicatorAuthentication,
         reset: bool,
     ) -> Result<Self> {
-        let Replicator {
-            inner: prev_inner,
-            free_callback_f,
-            boxed_callback_f,
-            validation,
-            c_callback_on_status_changed,
-            c_callback_on_documents_ended,
-            mode,
-        } = self;
-        mem::forget(self);
-        unsafe {
-            c4repl_stop(prev_inner.as_ptr());
-            c4repl_free(prev_inner.as_ptr());
-        }
+        self.recreate(db, vec![url.to_string()], 0, auth.clone(), reset)
+    }
+
+    /// Recreate replicator with the next URL from list passed to
+    /// `Replicator::new_with_urls` (after the last one the first is used).
+    /// Supposed to be called when state callback reports `Offline` or `Stopped`
+    /// because of network error
+    pub fn failover(mut self, db: &Database) -> Result<Self> {
+        let urls = mem::take(&mut self.urls);
+        let url_idx = (self.url_idx + 1) % urls.len();
+        info!("repl failover to {}", urls[url_idx]);
+        let auth = self.auth.clone();
+        self.recreate(db, urls, url_idx, auth, false)
+    }
+
+    /// Number of documents which replication ended with error
+    /// (reported to documents ended callback), since creation of replicator.
+    /// Document failed with transient error is counted on every attempt
+    #[inline]
+    pub fn document_error_count(&self) -> u64 {
+        self.doc_counters.errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of pulled revisions that conflicted with local ones,
+    /// since creation of replicator
+    #[inline]
+    pub fn conflict_count(&self) -> u64 {
+        self.doc_counters.conflicts.load(Ordering::Relaxed)
+    }
+
+    /// URL which is used by replicator now
+    #[inline]
+    pub fn current_url(&self) -> &str {
+        &self.urls[self.url_idx]
+    }
+
+    fn recreate(
+        mut self,
+        db: &Database,
+        urls: Vec<String>,
+        url_idx: usize,
+        auth: ReplicatorAuthentication,
+        reset: bool,
+    ) -> Result<Self> {
+        let boxed_callback_f = self
+            .boxed_callback_f
+            .take()
+            .ok_or_else(|| Error::LogicError("Replicator callbacks were already moved".into()))?;
+        // retry thread should be stopped before C4Replicator is freed
+        let auto_retry = self.auto_retry.take().map(RetryTask::stop);
+        let extra_headers = mem::take(&mut self.extra_headers);
+        let doc_counters = self.doc_counters.clone();
+        let reset = reset || self.reset_checkpoint;
+        let free_callback_f = self.free_callback_f;
+        let validation = self.validation;
+        let call_on_status_changed = self.c_callback_on_status_changed;
+        let call_on_documents_ended = self.c_callback_on_documents_ended;
+        let mode = self.mode;
+        let close_timeout = self.close_timeout;
+        unsafe { c4repl_stop(self.inner.as_ptr()) };
+        // frees C4Replicator, callbacks are kept because of `take` above
+        drop(self);
         let mut repl = Replicator::do_new(
             db,
-            url,
+            urls,
+            url_idx,
             auth,
+            extra_headers,
+            close_timeout,
+            doc_counters,
+            auto_retry,
             free_callback_f,
             boxed_callback_f,
             validation,
-            c_callback_on_status_changed,
-            c_callback_on_documents_ended,
+            call_on_status_changed,
+            call_on_documents_ended,
             mode,
         )?;
         repl.start(reset)?;
@@ -395,8 +854,13 @@ impl Replicator {
 
     fn do_new(
         db: &Database,
-        url: &str,
-        auth: &ReplicatorAuthentication,
+        urls: Vec<String>,
+        url_idx: usize,
+        auth: ReplicatorAuthentication,
+        extra_headers: Vec<String>,
+        close_timeout: Duration,
+        doc_counters: Arc<DocumentCounters>,
+        auto_retry: Option<Arc<AutoRetry>>,
         free_callback_f: unsafe fn(_: *mut c_void),
         boxed_callback_f: NonNull<c_void>,
         validation: C4ReplicatorValidationFunction,
@@ -406,6 +870,7 @@ impl Replicator {
     ) -> Result<Self> {
         use consts::*;
 
+        let url = urls[url_idx].as_str();
         let mut remote_addr = MaybeUninit::<C4Address>::uninit();
         let mut db_name = C4String::default();
         if !unsafe { c4address_fromURL(url.into(), remote_addr.as_mut_ptr(), &mut db_name) } {
@@ -413,12 +878,26 @@ impl Replicator {
         }
         let remote_addr = unsafe { remote_addr.assume_init() };
 
-        let options_dict: FLSliceResult = match auth {
+        // extra headers are array of "name:value" strings
+        let headers = extra_headers.as_slice();
+        let close_timeout_ms = u64::try_from(close_timeout.as_millis()).unwrap_or(u64::MAX);
+        // checkpoint is bound to URL, unless unique ID of remote database is set;
+        // not set for one URL to keep checkpoints of replicators created before
+        // multi-URL support, see `Replicator::new_with_urls`
+        let remote_unique_id: Option<&str> = if urls.len() > 1 {
+            Some(urls[0].as_str())
+        } else {
+            None
+        };
+        let options_dict: FLSliceResult = match &auth {
             ReplicatorAuthentication::SessionToken(token) => serde_fleece::fleece!({
                 kC4ReplicatorOptionAuthentication: {
                     kC4ReplicatorAuthType: kC4AuthTypeSession,
                     kC4ReplicatorAuthToken: token.as_str(),
-                }
+                },
+                kC4ReplicatorOptionExtraHeaders: headers,
+                kC4ReplicatorOptionRemoteDBUniqueID: remote_unique_id,
+                kSocketOptionCloseTimeoutMs: close_timeout_ms
             }),
             ReplicatorAuthentication::Basic { username, password } => {
                 serde_fleece::fleece!({
@@ -426,10 +905,17 @@ impl Replicator {
                         kC4ReplicatorAuthType: kC4AuthTypeBasic,
                         kC4ReplicatorAuthUserName: username.as_str(),
                         kC4ReplicatorAuthPassword: password.as_str()
-                    }
+                    },
+                    kC4ReplicatorOptionExtraHeaders: headers,
+                    kC4ReplicatorOptionRemoteDBUniqueID: remote_unique_id,
+                    kSocketOptionCloseTimeoutMs: close_timeout_ms
                 })
             }
-            ReplicatorAuthentication::None => serde_fleece::fleece!({}),
+            ReplicatorAuthentication::None => serde_fleece::fleece!({
+                kC4ReplicatorOptionExtraHeaders: headers,
+                kC4ReplicatorOptionRemoteDBUniqueID: remote_unique_id,
+                kSocketOptionCloseTimeoutMs: close_timeout_ms
+            }),
         }?;
 
         let mut collect_opt = C4ReplicationCollection {
@@ -469,29 +955,124 @@ impl Replicator {
             .map(|inner| Replicator {
                 inner,
                 free_callback_f,
-                boxed_callback_f,
+                boxed_callback_f: Some(boxed_callback_f),
                 validation,
                 c_callback_on_status_changed: call_on_status_changed,
                 c_callback_on_documents_ended: call_on_documents_ended,
                 mode,
+                extra_headers,
+                close_timeout,
+                reset_checkpoint: false,
+                auth,
+                urls,
+                url_idx,
+                doc_counters,
+                auto_retry: auto_retry.map(|control| RetryTask::spawn(control, inner)),
             })
             .ok_or_else(|| {
                 unsafe { free_callback_f(boxed_callback_f.as_ptr()) };
                 c4err.into()
             })
     }
+    /// Informs the replicator whether it's considered possible to reach the remote host with
+    /// the current network configuration. The default value is true. This only affects the
+    /// replicator's behavior while it's in the Offline state:
+    /// * Setting it to false will cancel any pending retry and prevent future automatic retries.
+    /// * Setting it back to true will initiate an immediate retry.
+    #[inline]
+    pub fn set_host_reachable(&mut self, reachable: bool) {
+        trace!(
+            "repl set_host_reachable {:?} {reachable}",
+            self.inner.as_ptr()
+        );
+        if !reachable {
+            self.cancel_auto_retry();
+        }
+        unsafe { c4repl_setHostReachable(self.inner.as_ptr(), reachable) };
+    }
+    /// Puts the replicator in or out of "suspended" state.
+    /// * Setting suspended=true causes the replicator to disconnect and enter Offline state;
+    ///   it will not attempt to reconnect while it's suspended.
+    /// * Setting suspended=false causes the replicator to attempt to reconnect, _if_ it was
+    ///   connected when suspended, and is still in Offline state.
+    #[inline]
+    pub fn set_suspended(&mut self, suspended: bool) {
+        trace!("repl set_suspended {:?} {suspended}", self.inner.as_ptr());
+        if suspended {
+            self.cancel_auto_retry();
+        }
+        unsafe { c4repl_setSuspended(self.inner.as_ptr(), suspended) };
+    }
     #[inline]
     pub fn stop(&mut self) {
         trace!("repl stop {:?}", self.inner.as_ptr());
+        self.cancel_auto_retry();
         unsafe { c4repl_stop(self.inner.as_ptr()) };
     }
     #[inline]
+    fn cancel_auto_retry(&self) {
+        if let Some(auto_retry) = self.auto_retry.as_ref() {
+            auto_retry.control.cancel();
+        }
+    }
+    #[inline]
     pub fn state(&self) -> ReplicatorState {
         self.status().into()
     }
     pub(crate) fn status(&self) -> C4ReplicatorStatus {
         unsafe { c4repl_getStatus(self.inner.as_ptr()) }
     }
+    /// HTTP response headers of websocket upgrade request (cookies, server version and so on),
+    /// as (name, value) pairs in order of core's dictionary, repeated header gives
+    /// several pairs with the same name; `None` if replicator is not connected yet.
+    /// Headers are copied, because core replaces them on reconnection
+    pub fn response_headers(&self) -> Result<Option<Vec<(String, String)>>> {
+        let data = unsafe { c4repl_getResponseHeaders(self.inner.as_ptr()) };
+        if data.buf.is_null() || data.size == 0 {
+            return Ok(None);
+        }
+        let data: &[u8] = data.into();
+        let data = data.to_vec();
+        let root = unsafe { FLValue_FromData(data.as_slice().into(), FLTrust::kFLUntrusted) };
+        let headers = unsafe { FLValue_AsDict(root) };
+        if headers.is_null() {
+            return Err(Error::LogicError(
+                "Response headers are not dictionary".into(),
+            ));
+        }
+        let mut ret = Vec::with_capacity(unsafe { FLDict_Count(headers) } as usize);
+        let mut it = MaybeUninit::<FLDictIterator>::uninit();
+        let mut it = unsafe {
+            FLDictIterator_Begin(headers, it.as_mut_ptr());
+            it.assume_init()
+        };
+        let mut all_ok = true;
+        while !unsafe { FLDictIterator_GetValue(&it) }.is_null() {
+            let Ok(name) = <&str>::try_from(unsafe { FLDictIterator_GetKeyString(&it) }) else {
+                all_ok = false;
+                break;
+            };
+            match unsafe { ValueRef::new(FLDictIterator_GetValue(&it)) } {
+                ValueRef::String(value) => ret.push((name.to_string(), value.to_string())),
+                ValueRef::Array(values) => {
+                    for i in 0..values.len() {
+                        if let ValueRef::String(value) = values.get(i) {
+                            ret.push((name.to_string(), value.to_string()));
+                        }
+                    }
+                }
+                _ => {}
+            }
+            if !unsafe { FLDictIterator_Next(&mut it) } {
+                break;
+            }
+        }
+        unsafe { FLDictIterator_End(&mut it) };
+        if !all_ok {
+            return Err(Error::InvalidUtf8);
+        }
+        Ok(Some(ret))
+    }
 }
 
 /// Represents the current progress of a replicator.
@@ -515,6 +1096,158 @@ pub enum ReplicatorState {
     Busy(ReplicatorProgress),
 }
 
+impl fmt::Display for ReplicatorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplicatorState::Stopped(err) => write!(f, "stopped: {err}"),
+            ReplicatorState::Offline => write!(f, "offline"),
+            ReplicatorState::Connecting => write!(f, "connecting"),
+            ReplicatorState::Idle => write!(f, "idle"),
+            ReplicatorState::Busy(progress) if progress.unitsTotal > 0 => {
+                let percent = (progress.unitsCompleted as f64 / progress.unitsTotal as f64) * 100.;
+                write!(f, "busy ({percent:.0}%)")
+            }
+            ReplicatorState::Busy(_) => write!(f, "busy"),
+        }
+    }
+}
+
+/// Incoming revision passed to filter set via
+/// `ReplicatorParameters::with_replicated_doc_filter`,
+/// valid only during the call of filter
+pub struct ReplicatedDoc<'a> {
+    coll_spec: C4CollectionSpec,
+    doc_id: C4String,
+    rev_id: C4String,
+    flags: C4RevisionFlags,
+    body: FLDict,
+    marker: PhantomData<&'a FLDict>,
+}
+
+impl<'a> ReplicatedDoc<'a> {
+    #[inline]
+    pub fn collection_name(&self) -> Result<&'a str> {
+        self.coll_spec
+            .name
+            .try_into()
+            .map_err(|_| Error::InvalidUtf8)
+    }
+    #[inline]
+    pub fn doc_id(&self) -> Result<&'a str> {
+        self.doc_id.try_into().map_err(|_| Error::InvalidUtf8)
+    }
+    #[inline]
+    pub fn rev_id(&self) -> Result<&'a str> {
+        self.rev_id.try_into().map_err(|_| Error::InvalidUtf8)
+    }
+    #[inline]
+    pub fn flags(&self) -> C4RevisionFlags {
+        self.flags
+    }
+    /// Revision is deletion or purge, such revisions have no body
+    #[inline]
+    pub fn is_deleted(&self) -> bool {
+        (self.flags & (C4RevisionFlags::kRevDeleted | C4RevisionFlags::kRevPurged))
+            != C4RevisionFlags(0)
+    }
+    /// Body of revision, `None` if body is null
+    #[inline]
+    pub fn body(&self) -> Option<serde_fleece::Dict<'a>> {
+        serde_fleece::Dict::new(&self.body)
+    }
+    /// Get string property of body, `None` if there is no such property,
+    /// it is not string or body is null
+    #[inline]
+    pub fn get_as_str(&self, key: &str) -> Option<&'a str> {
+        match unsafe { ValueRef::new(FLDict_Get(self.body, key.into())) } {
+            ValueRef::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    /// Decode body of revision, returns error if body is null
+    pub fn decode<T: Deserialize<'a>>(&self) -> Result<T> {
+        let body = self
+            .body()
+            .ok_or_else(|| Error::LogicError("replicated document has no body".into()))?;
+        serde_fleece::from_fl_dict(body).map_err(Error::from)
+    }
+    /// Decode body of revision like `decode`, but keys of body are
+    /// decoded with database's shared keys, see `Database::fleece_shared_keys`
+    pub fn decode_with_shared_keys<T: Deserialize<'a>>(
+        &self,
+        shared_keys: &serde_fleece::SharedKeys,
+    ) -> Result<T> {
+        let body = self
+            .body()
+            .ok_or_else(|| Error::LogicError("replicated document has no body".into()))?;
+        serde_fleece::from_fl_dict_with_shared_keys(body, shared_keys).map_err(Error::from)
+    }
+}
+
+/// Replication status of one document, wrapper around `C4DocumentEnded`
+#[derive(Clone, Copy)]
+pub struct DocumentEnded<'a> {
+    inner: &'a C4DocumentEnded,
+}
+
+impl<'a> From<&'a C4DocumentEnded> for DocumentEnded<'a> {
+    #[inline]
+    fn from(inner: &'a C4DocumentEnded) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a> DocumentEnded<'a> {
+    #[inline]
+    pub fn doc_id(&self) -> Result<&'a str> {
+        self.inner
+            .docID
+            .as_fl_slice()
+            .try_into()
+            .map_err(|_| Error::InvalidUtf8)
+    }
+    #[inline]
+    pub fn rev_id(&self) -> Result<&'a str> {
+        self.inner
+            .revID
+            .as_fl_slice()
+            .try_into()
+            .map_err(|_| Error::InvalidUtf8)
+    }
+    #[inline]
+    pub fn flags(&self) -> C4RevisionFlags {
+        self.inner.flags
+    }
+    #[inline]
+    pub fn sequence(&self) -> u64 {
+        self.inner.sequence
+    }
+    /// Pulled revision is in conflict with local one
+    #[inline]
+    pub fn is_conflict(&self) -> bool {
+        (self.inner.flags & C4RevisionFlags::kRevIsConflict) != C4RevisionFlags(0)
+    }
+    /// Reason why replication of document failed, if it failed
+    #[inline]
+    pub fn error(&self) -> Option<Error> {
+        if self.inner.error.code != 0 {
+            Some(self.inner.error.into())
+        } else {
+            None
+        }
+    }
+    /// If true, replicator will retry replication of document later
+    #[inline]
+    pub fn error_is_transient(&self) -> bool {
+        self.inner.errorIsTransient
+    }
+    /// Access to raw `C4DocumentEnded`
+    #[inline]
+    pub fn as_raw(&self) -> &'a C4DocumentEnded {
+        self.inner
+    }
+}
+
 unsafe fn free_boxed_value<T>(p: *mut c_void) {
     drop(Box::from_raw(p as *mut T));
 }
@@ -587,13 +1320,26 @@ pub(crate) mod consts {
     }
     define_const_slice!(
         kC4ReplicatorOptionExtraHeaders,
+        kC4ReplicatorOptionRemoteDBUniqueID,
         kC4ReplicatorOptionCookies,
         kC4SocketOptionWSProtocols,
     );
+
+    /// Not part of couchbase-lite-core, options dictionary is passed
+    /// to socket's open, so we use it to configure our socket implementation
+    pub(crate) const kSocketOptionCloseTimeoutMs: &[u8] = b"rustCloseTimeoutMs";
 }
 
+pub use socket_factory::{SocketFactoryImpl, SocketHandle};
+
 static WEBSOCKET_IMPL: Once = Once::new();
 
+pub(crate) fn init_custom_socket_impl<F: SocketFactoryImpl>(factory: F) {
+    WEBSOCKET_IMPL.call_once(|| {
+        socket_factory::c4socket_init(factory);
+    });
+}
+
 #[cfg(feature = "use-couchbase-lite-websocket")]
 pub(crate) fn init_builtin_socket_impl() {
     WEBSOCKET_IMPL.call_once(|| {
@@ -601,9 +1347,31 @@ pub(crate) fn init_builtin_socket_impl() {
     });
 }
 
+#[cfg(feature = "use-std-websocket")]
+pub(crate) fn init_std_socket_impl() {
+    init_custom_socket_impl(std_socket::StdSocketFactory);
+}
+
 #[cfg(feature = "use-tokio-websocket")]
 pub(crate) fn init_tokio_socket_impl(handle: tokio::runtime::Handle) {
     WEBSOCKET_IMPL.call_once(|| {
-        tokio_socket::c4socket_init(handle);
+        tokio_socket::c4socket_init(tokio_socket::SocketFactoryContext {
+            handle,
+            #[cfg(feature = "use-rustls")]
+            tls_config: None,
+        });
+    });
+}
+
+#[cfg(all(feature = "use-tokio-websocket", feature = "use-rustls"))]
+pub(crate) fn init_tokio_rustls_socket_impl(
+    handle: tokio::runtime::Handle,
+    tls_config: std::sync::Arc<rustls::ClientConfig>,
+) {
+    WEBSOCKET_IMPL.call_once(|| {
+        tokio_socket::c4socket_init(tokio_socket::SocketFactoryContext {
+            handle,
+            tls_config: Some(tls_config),
+        });
     });
 }
@@ -0,0 +1,236 @@
+use crate::{
+    error::c4error_init,
+    ffi::{
+        c4Socket_getNativeHandle, c4Socket_setNativeHandle, c4socket_closeRequested,
+        c4socket_closed, c4socket_completedWrite, c4socket_gotHTTPResponse, c4socket_opened,
+        c4socket_received, c4socket_registerFactory, C4Address, C4Error, C4Slice, C4SliceResult,
+        C4Socket, C4SocketFactory, C4SocketFraming, C4String, FLTrust, FLValue_FromData,
+    },
+    value::{ValueRef, ValueRefDict},
+};
+use log::{error, trace};
+use std::{
+    mem,
+    os::raw::{c_int, c_void},
+    panic::{catch_unwind, AssertUnwindSafe},
+    process::abort,
+    ptr::NonNull,
+    str,
+};
+
+/// Transport used by replicator instead of builtin websocket implementations.
+///
+/// Factory is registered once per process via
+/// [`Database::register_socket_factory`](crate::Database::register_socket_factory),
+/// for every connection `open` is called and returned `Socket` lives till
+/// core disposes the connection. All methods are called from
+/// LiteCore's threads, so they should not block.
+pub trait SocketFactoryImpl: Send + Sync + 'static {
+    /// Per-connection state
+    type Socket: Send + 'static;
+
+    /// If not `kC4NoFraming` LiteCore handles WebSocket framing itself,
+    /// and socket only transfers raw bytes
+    fn framing(&self) -> C4SocketFraming {
+        C4SocketFraming::kC4NoFraming
+    }
+
+    /// Start connecting to `url`, result should be reported via
+    /// [`SocketHandle::opened`] or [`SocketHandle::closed`].
+    /// `options` is valid only during this call. Events should not be reported
+    /// before `open` returns, because `Socket` is not yet attached to `socket`.
+    fn open(&self, socket: SocketHandle, url: &str, options: Option<ValueRefDict>) -> Self::Socket;
+
+    /// Send `data`, after that [`SocketHandle::completed_write`] should be called
+    fn write(&self, sock: &mut Self::Socket, socket: SocketHandle, data: &[u8]);
+
+    /// LiteCore processed `byte_count` bytes of received data
+    fn completed_receive(&self, sock: &mut Self::Socket, socket: SocketHandle, byte_count: usize);
+
+    /// Initiate close handshake, should be finished with [`SocketHandle::closed`]
+    fn request_close(
+        &self,
+        sock: &mut Self::Socket,
+        socket: SocketHandle,
+        status: i32,
+        message: &str,
+    );
+}
+
+/// Reference to LiteCore's socket, used to notify it about socket's events
+#[derive(Debug, Clone, Copy)]
+pub struct SocketHandle(NonNull<C4Socket>);
+
+unsafe impl Send for SocketHandle {}
+unsafe impl Sync for SocketHandle {}
+
+impl SocketHandle {
+    /// Connection established
+    pub fn opened(&self) {
+        unsafe { c4socket_opened(self.0.as_ptr()) };
+    }
+    /// Report HTTP response of WebSocket handshake,
+    /// `headers_fleece` is fleece encoded dictionary
+    pub fn got_http_response(&self, status: i32, headers_fleece: &[u8]) {
+        unsafe { c4socket_gotHTTPResponse(self.0.as_ptr(), status, headers_fleece.into()) };
+    }
+    /// Pass received data to LiteCore
+    pub fn received(&self, data: &[u8]) {
+        unsafe { c4socket_received(self.0.as_ptr(), data.into()) };
+    }
+    /// `byte_count` bytes passed to `write` were sent
+    pub fn completed_write(&self, byte_count: usize) {
+        unsafe { c4socket_completedWrite(self.0.as_ptr(), byte_count) };
+    }
+    /// Peer requested close
+    pub fn close_requested(&self, status: i32, message: &str) {
+        unsafe { c4socket_closeRequested(self.0.as_ptr(), status, message.into()) };
+    }
+    /// Socket closed, `None` means normal close
+    pub fn closed(&self, err: Option<C4Error>) {
+        unsafe { c4socket_closed(self.0.as_ptr(), err.unwrap_or_else(c4error_init)) };
+    }
+}
+
+struct NativeSocket<F: SocketFactoryImpl> {
+    factory: &'static F,
+    sock: F::Socket,
+}
+
+pub(crate) fn c4socket_init<F: SocketFactoryImpl>(factory: F) {
+    let framing = factory.framing();
+    let factory: &'static F = Box::leak(Box::new(factory));
+    let sock_factory = C4SocketFactory {
+        framing,
+        context: factory as *const F as *mut c_void,
+        open: Some(sf_open::<F>),
+        write: Some(sf_write::<F>),
+        completedReceive: Some(sf_completed_receive::<F>),
+        close: None,
+        requestClose: Some(sf_request_close::<F>),
+        dispose: Some(sf_dispose::<F>),
+    };
+    unsafe { c4socket_registerFactory(sock_factory) };
+}
+
+fn call_no_unwind<R>(f: impl FnOnce() -> R) -> R {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(x) => x,
+        Err(err) => {
+            error!("socket factory: panic during callback: {err:?}");
+            abort();
+        }
+    }
+}
+
+unsafe fn native_socket<'a, F: SocketFactoryImpl>(
+    c4sock: *mut C4Socket,
+) -> (SocketHandle, &'a mut NativeSocket<F>) {
+    let c4sock = NonNull::new(c4sock).expect("c4sock is null");
+    let native = c4Socket_getNativeHandle(c4sock.as_ptr()) as *mut NativeSocket<F>;
+    assert!(!native.is_null());
+    (SocketHandle(c4sock), &mut *native)
+}
+
+unsafe extern "C" fn sf_open<F: SocketFactoryImpl>(
+    c4sock: *mut C4Socket,
+    addr: *const C4Address,
+    options: C4Slice,
+    context: *mut c_void,
+) {
+    assert!(!context.is_null());
+    let factory: &'static F = &*(context as *const F);
+    let c4sock = NonNull::new(c4sock).expect("c4sock is null");
+    assert!(c4Socket_getNativeHandle(c4sock.as_ptr()).is_null());
+    assert!(!addr.is_null());
+    let url = c4address_to_url(&*addr);
+    trace!("c4sock {c4sock:?}: open was called with url: {url}");
+    let options = match ValueRef::new(FLValue_FromData(options, FLTrust::kFLTrusted)) {
+        ValueRef::Dict(dict) => Some(dict),
+        _ => None,
+    };
+    let sock = call_no_unwind(|| factory.open(SocketHandle(c4sock), &url, options));
+    let native = Box::new(NativeSocket { factory, sock });
+    c4Socket_setNativeHandle(c4sock.as_ptr(), Box::into_raw(native) as *mut c_void);
+}
+
+unsafe extern "C" fn sf_write<F: SocketFactoryImpl>(
+    c4sock: *mut C4Socket,
+    allocated_data: C4SliceResult,
+) {
+    trace!("c4sock {c4sock:?}: write {} bytes", allocated_data.size);
+    let (handle, native) = native_socket::<F>(c4sock);
+    call_no_unwind(|| {
+        native
+            .factory
+            .write(&mut native.sock, handle, allocated_data.as_bytes())
+    });
+}
+
+unsafe extern "C" fn sf_completed_receive<F: SocketFactoryImpl>(
+    c4sock: *mut C4Socket,
+    byte_count: usize,
+) {
+    trace!("c4sock {c4sock:?}: completed receive {byte_count} bytes");
+    let (handle, native) = native_socket::<F>(c4sock);
+    call_no_unwind(|| {
+        native
+            .factory
+            .completed_receive(&mut native.sock, handle, byte_count)
+    });
+}
+
+unsafe extern "C" fn sf_request_close<F: SocketFactoryImpl>(
+    c4sock: *mut C4Socket,
+    status: c_int,
+    message: C4String,
+) {
+    trace!("c4sock {c4sock:?}: request close, status {status}");
+    let (handle, native) = native_socket::<F>(c4sock);
+    let message = str::from_utf8(message.into()).unwrap_or("<invalid utf-8>");
+    call_no_unwind(|| {
+        native
+            .factory
+            .request_close(&mut native.sock, handle, status, message)
+    });
+}
+
+unsafe extern "C" fn sf_dispose<F: SocketFactoryImpl>(c4sock: *mut C4Socket) {
+    trace!("c4sock {c4sock:?}: dispose");
+    assert!(!c4sock.is_null());
+    let native = c4Socket_getNativeHandle(c4sock) as *mut NativeSocket<F>;
+    if !native.is_null() {
+        let native = Box::from_raw(native);
+        call_no_unwind(|| mem::drop(native));
+    }
+}
+
+fn c4address_to_url(addr: &C4Address) -> String {
+    let part = |s: C4String| String::from_utf8_lossy(<&[u8]>::from(s)).into_owned();
+    format!(
+        "{}://{}:{}{}",
+        part(addr.scheme),
+        part(addr.hostname),
+        addr.port,
+        part(addr.path)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_c4address_to_url() {
+        let addr = C4Address {
+            scheme: "wss".into(),
+            hostname: "example.com".into(),
+            port: 4984,
+            path: "/db/_blipsync".into(),
+        };
+        assert_eq!(
+            "wss://example.com:4984/db/_blipsync",
+            c4address_to_url(&addr)
+        );
+    }
+}
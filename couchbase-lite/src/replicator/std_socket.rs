@@ -0,0 +1,553 @@
+//! Websocket transport based on blocking `tungstenite`,
+//! every connection is served by own reader and writer threads
+
+use crate::{
+    ffi::{
+        c4error_make, C4Error, C4ErrorDomain, C4NetworkErrorCode, C4WebSocketCloseCode, FLSlice,
+    },
+    replicator::{
+        consts::*,
+        socket_factory::{SocketFactoryImpl, SocketHandle},
+        DEFAULT_CLOSE_TIMEOUT,
+    },
+    value::{ValueRef, ValueRefDict},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use log::{error, info, trace, warn};
+use std::{
+    collections::HashMap,
+    io,
+    net::{TcpStream, ToSocketAddrs},
+    os::raw::c_int,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, MutexGuard,
+    },
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    client::{uri_mode, IntoClientRequest},
+    error::UrlError,
+    handshake::{client::Request, client::Response, HandshakeError},
+    http::{header::HeaderName, HeaderValue},
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    stream::{MaybeTlsStream, Mode},
+    Message, WebSocket,
+};
+
+/// How often reader thread wakes up to check close timeout
+/// while waiting for incoming data
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Timeout for every stage of connection: TCP connect, TLS and HTTP handshakes
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+
+type WsStream = WebSocket<MaybeTlsStream<TcpStream>>;
+
+pub(crate) struct StdSocketFactory;
+
+pub(crate) struct StdSocket {
+    commands: mpsc::Sender<Command>,
+    unconfirmed_bytes: Arc<AtomicUsize>,
+    reader: Thread,
+}
+
+enum Command {
+    Write(Vec<u8>),
+    Close(c_int, String),
+}
+
+impl SocketFactoryImpl for StdSocketFactory {
+    type Socket = StdSocket;
+
+    fn open(&self, socket: SocketHandle, url: &str, options: Option<ValueRefDict>) -> StdSocket {
+        let request = build_request(url, options);
+        let close_timeout = options
+            .map(|opts| match opts.get(kSocketOptionCloseTimeoutMs.into()) {
+                ValueRef::UnsignedInt(ms) => Duration::from_millis(ms),
+                ValueRef::SignedInt(ms) if ms >= 0 => Duration::from_millis(ms as u64),
+                _ => DEFAULT_CLOSE_TIMEOUT,
+            })
+            .unwrap_or(DEFAULT_CLOSE_TIMEOUT);
+        let (tx, rx) = mpsc::channel();
+        let unconfirmed_bytes = Arc::new(AtomicUsize::new(0));
+        let io_unconfirmed_bytes = unconfirmed_bytes.clone();
+        let reader = thread::Builder::new()
+            .name("cbl-websocket".into())
+            .spawn(move || {
+                let ret = request.and_then(|request| {
+                    io_loop(socket, request, rx, io_unconfirmed_bytes, close_timeout)
+                });
+                match ret {
+                    Ok(true) => socket.closed(None),
+                    Ok(false) => {}
+                    Err(err) => socket.closed(Some(err)),
+                }
+            })
+            .expect("Can not spawn websocket I/O thread");
+        StdSocket {
+            commands: tx,
+            unconfirmed_bytes,
+            reader: reader.thread().clone(),
+        }
+    }
+
+    fn write(&self, sock: &mut StdSocket, socket: SocketHandle, data: &[u8]) {
+        if sock.commands.send(Command::Write(data.to_vec())).is_err() {
+            warn!("c4sock {socket:?}: write, but I/O thread gone");
+        }
+    }
+
+    fn completed_receive(&self, sock: &mut StdSocket, _socket: SocketHandle, byte_count: usize) {
+        let _ = sock
+            .unconfirmed_bytes
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                Some(n.saturating_sub(byte_count))
+            });
+        sock.reader.unpark();
+    }
+
+    fn request_close(
+        &self,
+        sock: &mut StdSocket,
+        socket: SocketHandle,
+        status: i32,
+        message: &str,
+    ) {
+        if sock
+            .commands
+            .send(Command::Close(status, message.to_string()))
+            .is_err()
+        {
+            warn!("c4sock {socket:?}: requestClose, but I/O thread gone");
+        }
+    }
+}
+
+/// Connection shared by reader and writer threads.
+/// Reader holds the lock only when there is data to read,
+/// so writes are not delayed by waiting for incoming data
+struct Connection {
+    ws: WsStream,
+    /// close error and deadline for waiting close ack from server
+    client_close: Option<(C4Error, Instant)>,
+    server_close: bool,
+    /// Writer thread finished connection with this error
+    write_error: Option<C4Error>,
+    /// LiteCore disposed socket, commands channel is closed
+    disposed: bool,
+    /// `closed` was reported to LiteCore, socket should not be used anymore
+    closed: bool,
+}
+
+fn lock(conn: &Mutex<Connection>) -> MutexGuard<Connection> {
+    conn.lock().expect("websocket connection lock failed")
+}
+
+/// Returns `Ok(false)` if socket was already disposed by LiteCore,
+/// so closing should not be reported
+fn io_loop(
+    socket: SocketHandle,
+    request: Request,
+    commands: mpsc::Receiver<Command>,
+    unconfirmed_bytes: Arc<AtomicUsize>,
+    close_timeout: Duration,
+) -> Result<bool, C4Error> {
+    trace!("c4sock {socket:?}: connecting to {}", request.uri());
+    let (ws, http_resp) = connect(request)?;
+    let headers: HashMap<&str, &str> = http_resp
+        .headers()
+        .iter()
+        .filter_map(|(k, v)| Some((k.as_str(), v.to_str().ok()?)))
+        .collect();
+    let headers = serde_fleece::to_fl_slice_result(&headers).map_err(|err| {
+        c4_err(
+            C4ErrorDomain::WebSocketDomain,
+            C4WebSocketCloseCode::kWebSocketCloseBadMessageFormat.0,
+            &err.to_string(),
+        )
+    })?;
+    socket.got_http_response(http_resp.status().as_u16().into(), headers.as_bytes());
+    // reader waits for data on cloned socket without lock of connection
+    let raw = tcp_stream(ws.get_ref())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "not TCP stream"))
+        .and_then(|s| {
+            s.set_read_timeout(Some(POLL_INTERVAL))?;
+            s.set_write_timeout(None)?;
+            s.try_clone()
+        })
+        .map_err(|err| io_err_to_c4_err(&err))?;
+    let conn = Arc::new(Mutex::new(Connection {
+        ws,
+        client_close: None,
+        server_close: false,
+        write_error: None,
+        disposed: false,
+        closed: false,
+    }));
+    {
+        let conn = conn.clone();
+        thread::Builder::new()
+            .name("cbl-websocket-writer".into())
+            .spawn(move || write_loop(socket, &conn, commands, close_timeout))
+            .map_err(|err| io_err_to_c4_err(&err))?;
+    }
+    socket.opened();
+    let ret = read_loop(socket, &conn, &raw, &unconfirmed_bytes);
+    lock(&conn).closed = true;
+    ret
+}
+
+fn read_loop(
+    socket: SocketHandle,
+    conn: &Mutex<Connection>,
+    raw: &TcpStream,
+    unconfirmed_bytes: &AtomicUsize,
+) -> Result<bool, C4Error> {
+    loop {
+        {
+            let mut conn = lock(conn);
+            if conn.disposed {
+                info!("c4sock {socket:?}: socket disposed, stop I/O thread");
+                return Ok(false);
+            }
+            if let Some(err) = conn.write_error.take() {
+                return Err(err);
+            }
+            if let Some((err, deadline)) = conn.client_close {
+                if Instant::now() >= deadline {
+                    warn!("c4sock {socket:?}: timeout for waiting close ack expired");
+                    return Err(err);
+                }
+            }
+        }
+        if unconfirmed_bytes.load(Ordering::Acquire) != 0 {
+            // `completed_receive` wakes us up
+            thread::park_timeout(POLL_INTERVAL);
+            continue;
+        }
+        match raw.peek(&mut [0u8; 1]) {
+            Ok(_) => {}
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(err) => return Err(io_err_to_c4_err(&err)),
+        }
+        // even without new data in socket, there can be buffered messages or
+        // pending pong, so try to read without blocking anyway
+        let mut conn = lock(conn);
+        set_nonblocking(&conn, true)?;
+        let ret = read_available(socket, &mut conn, unconfirmed_bytes);
+        set_nonblocking(&conn, false)?;
+        if let Some(ret) = ret? {
+            return Ok(ret);
+        }
+    }
+}
+
+/// Read messages till there is no data or LiteCore should confirm received one.
+/// Returns `Some` if connection was closed
+fn read_available(
+    socket: SocketHandle,
+    conn: &mut Connection,
+    unconfirmed_bytes: &AtomicUsize,
+) -> Result<Option<bool>, C4Error> {
+    while unconfirmed_bytes.load(Ordering::Acquire) == 0 {
+        match conn.ws.read() {
+            Ok(m @ Message::Text(_)) | Ok(m @ Message::Binary(_)) => {
+                let data = m.into_data();
+                unconfirmed_bytes.store(data.len(), Ordering::Release);
+                socket.received(&data);
+            }
+            Ok(Message::Close(close_frame)) => {
+                if let Some((err, _)) = conn.client_close {
+                    trace!("c4sock {socket:?}: close ack was received");
+                    return Err(err);
+                }
+                let (code, reason) = close_frame
+                    .map(|x| (u16::from(x.code) as c_int, x.reason.into_owned()))
+                    .unwrap_or_else(|| (-1, String::new()));
+                info!("c4sock {socket:?}: close frame was received: {code} {reason}");
+                if !conn.server_close {
+                    conn.server_close = true;
+                    socket.close_requested(code, &reason);
+                }
+            }
+            Ok(Message::Ping(_)) | Ok(Message::Pong(_)) | Ok(Message::Frame(_)) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                // pending pong or close reply may wait in write buffer
+                match conn.ws.flush() {
+                    Ok(()) => {}
+                    Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                    }
+                    Err(err) => return Err(tungstenite_err_to_c4_err(err)),
+                }
+                return Ok(None);
+            }
+            Err(tungstenite::Error::ConnectionClosed) => {
+                return conn
+                    .client_close
+                    .map_or(Ok(Some(true)), |(err, _)| Err(err));
+            }
+            Err(err) => return Err(tungstenite_err_to_c4_err(err)),
+        }
+    }
+    Ok(None)
+}
+
+/// Sends data and close request from LiteCore as soon as they arrive,
+/// socket is in blocking mode while the lock of connection is not held by reader
+fn write_loop(
+    socket: SocketHandle,
+    conn: &Mutex<Connection>,
+    commands: mpsc::Receiver<Command>,
+    close_timeout: Duration,
+) {
+    for cmd in commands {
+        let mut conn = lock(conn);
+        if conn.closed || conn.write_error.is_some() {
+            return;
+        }
+        match cmd {
+            Command::Write(data) => {
+                let n = data.len();
+                match conn.ws.send(Message::Binary(data)) {
+                    // under the lock, so it is not reported after `closed`
+                    Ok(()) => socket.completed_write(n),
+                    Err(err) => conn.write_error = Some(tungstenite_err_to_c4_err(err)),
+                }
+            }
+            Command::Close(status, reason) => {
+                let err = c4_err(C4ErrorDomain::WebSocketDomain, status, &reason);
+                if conn.server_close {
+                    // tungstenite already replied to server's close frame
+                    let _ = conn.ws.flush();
+                    conn.write_error = Some(err);
+                    continue;
+                }
+                trace!("c4sock {socket:?}: sending close message");
+                let code: CloseCode = u16::try_from(status).unwrap_or(1).into();
+                if let Err(send_err) = conn.ws.close(Some(CloseFrame {
+                    code,
+                    reason: reason.into(),
+                })) {
+                    error!("c4sock {socket:?}: requestClose, send failure: {send_err}");
+                    conn.write_error = Some(err);
+                    continue;
+                }
+                conn.client_close = Some((err, Instant::now() + close_timeout));
+            }
+        }
+    }
+    lock(conn).disposed = true;
+}
+
+/// Blocking connect, where every stage is limited by `CONNECT_TIMEOUT`,
+/// so close request from LiteCore is not ignored for long
+fn connect(request: Request) -> Result<(WsStream, Response), C4Error> {
+    let uri = request.uri();
+    let mode = uri_mode(uri).map_err(tungstenite_err_to_c4_err)?;
+    let host = uri
+        .host()
+        .ok_or_else(|| tungstenite_err_to_c4_err(UrlError::NoHostName.into()))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(match mode {
+        Mode::Plain => 80,
+        Mode::Tls => 443,
+    });
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, format!("can not resolve {host}"));
+    let mut stream = None;
+    for addr in (host, port)
+        .to_socket_addrs()
+        .map_err(|err| io_err_to_c4_err(&err))?
+    {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(s) => {
+                stream = Some(s);
+                break;
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    let stream = stream.ok_or_else(|| io_err_to_c4_err(&last_err))?;
+    stream
+        .set_read_timeout(Some(CONNECT_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(CONNECT_TIMEOUT)))
+        .and_then(|()| stream.set_nodelay(true))
+        .map_err(|err| io_err_to_c4_err(&err))?;
+
+    #[cfg(any(feature = "use-native-tls", feature = "use-rustls"))]
+    let ret = tungstenite::client_tls(request, stream);
+    #[cfg(not(any(feature = "use-native-tls", feature = "use-rustls")))]
+    let ret = match mode {
+        Mode::Plain => tungstenite::client(request, MaybeTlsStream::Plain(stream)),
+        Mode::Tls => {
+            return Err(tungstenite_err_to_c4_err(
+                UrlError::TlsFeatureNotEnabled.into(),
+            ))
+        }
+    };
+    ret.map_err(|err| match err {
+        HandshakeError::Failure(err) => tungstenite_err_to_c4_err(err),
+        HandshakeError::Interrupted(_) => c4_err(
+            C4ErrorDomain::NetworkDomain,
+            C4NetworkErrorCode::kC4NetErrTimeout.0,
+            "websocket handshake timeout",
+        ),
+    })
+}
+
+fn tcp_stream(stream: &MaybeTlsStream<TcpStream>) -> Option<&TcpStream> {
+    match stream {
+        MaybeTlsStream::Plain(s) => Some(s),
+        #[cfg(feature = "use-native-tls")]
+        MaybeTlsStream::NativeTls(s) => Some(s.get_ref()),
+        #[cfg(feature = "use-rustls")]
+        MaybeTlsStream::Rustls(s) => Some(s.get_ref()),
+        _ => None,
+    }
+}
+
+fn set_nonblocking(conn: &Connection, nonblocking: bool) -> Result<(), C4Error> {
+    tcp_stream(conn.ws.get_ref())
+        .map_or(Ok(()), |s| s.set_nonblocking(nonblocking))
+        .map_err(|err| io_err_to_c4_err(&err))
+}
+
+fn build_request(url: &str, options: Option<ValueRefDict>) -> Result<Request, C4Error> {
+    let invalid_param = |msg: &str| {
+        c4_err(
+            C4ErrorDomain::LiteCoreDomain,
+            crate::ffi::C4ErrorCode::kC4ErrorInvalidParameter.0,
+            msg,
+        )
+    };
+    let mut request = url
+        .into_client_request()
+        .map_err(tungstenite_err_to_c4_err)?;
+    let Some(options) = options else {
+        return Ok(request);
+    };
+    let mut add_header = |name: &str, value: &str| -> Result<(), C4Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| invalid_param(&err.to_string()))?;
+        let value = HeaderValue::from_str(value).map_err(|err| invalid_param(&err.to_string()))?;
+        request.headers_mut().insert(name, value);
+        Ok(())
+    };
+
+    if let ValueRef::Array(headers) = options.get(kC4ReplicatorOptionExtraHeaders.into()) {
+        for i in 0..headers.len() {
+            let ValueRef::String(header) = headers.get(i) else {
+                return Err(invalid_param("Header value was not string"));
+            };
+            let (name, value) = header.split_once(':').ok_or_else(|| {
+                invalid_param(&format!(
+                    "Header value {header} does not appear to be valid"
+                ))
+            })?;
+            add_header(name, value)?;
+        }
+    }
+
+    let mut cookies = Vec::<String>::new();
+    if let ValueRef::Dict(auth) = options.get(kC4ReplicatorOptionAuthentication.into()) {
+        let auth_type = match auth.get(kC4ReplicatorAuthType.into()) {
+            ValueRef::String(auth_type) => auth_type,
+            _ => kC4AuthTypeBasic,
+        };
+        if auth_type == kC4AuthTypeBasic {
+            let (ValueRef::String(username), ValueRef::String(password)) = (
+                auth.get(kC4ReplicatorAuthUserName.into()),
+                auth.get(kC4ReplicatorAuthPassword.into()),
+            ) else {
+                return Err(invalid_param(
+                    "Can not get username or password for basic auth type",
+                ));
+            };
+            let mut header = "Basic ".to_string();
+            BASE64_STANDARD.encode_string(format!("{username}:{password}"), &mut header);
+            add_header("Authorization", &header)?;
+        } else if auth_type == kC4AuthTypeSession {
+            if let ValueRef::String(token) = auth.get(kC4ReplicatorAuthToken.into()) {
+                cookies.push(format!("SyncGatewaySession={token}"));
+            }
+        } else {
+            return Err(invalid_param(&format!(
+                "Invalid {auth_type}, only Basic and Session auth types are implemented"
+            )));
+        }
+    }
+    if let ValueRef::String(cookie) = options.get(kC4ReplicatorOptionCookies.into()) {
+        cookies.push(cookie.to_string());
+    }
+    if let ValueRef::String(protocol) = options.get(kC4SocketOptionWSProtocols.into()) {
+        add_header("Sec-WebSocket-Protocol", protocol)?;
+    }
+    if !cookies.is_empty() {
+        add_header("Cookie", &cookies.join(";"))?;
+    }
+    Ok(request)
+}
+
+fn c4_err(domain: C4ErrorDomain, code: c_int, msg: &str) -> C4Error {
+    unsafe { c4error_make(domain, code, FLSlice::from(msg)) }
+}
+
+fn io_err_to_c4_err(err: &io::Error) -> C4Error {
+    let code = match err.kind() {
+        io::ErrorKind::TimedOut => C4NetworkErrorCode::kC4NetErrTimeout,
+        io::ErrorKind::ConnectionRefused => C4NetworkErrorCode::kC4NetErrConnectionRefused,
+        io::ErrorKind::ConnectionReset => C4NetworkErrorCode::kC4NetErrConnectionReset,
+        _ => C4NetworkErrorCode::kC4NumNetErrorCodesPlus1,
+    };
+    c4_err(C4ErrorDomain::NetworkDomain, code.0, &err.to_string())
+}
+
+fn tungstenite_err_to_c4_err(err: tungstenite::Error) -> C4Error {
+    use tungstenite::error::Error::*;
+    let msg = err.to_string();
+    let (domain, code) = match err {
+        ConnectionClosed => (
+            C4ErrorDomain::WebSocketDomain,
+            C4WebSocketCloseCode::kWebSocketCloseNormal.0,
+        ),
+        AlreadyClosed => (
+            C4ErrorDomain::WebSocketDomain,
+            C4WebSocketCloseCode::kWebSocketCloseFirstAvailable.0,
+        ),
+        Io(ref err) => return io_err_to_c4_err(err),
+        Utf8 | HttpFormat(_) => (
+            C4ErrorDomain::WebSocketDomain,
+            C4WebSocketCloseCode::kWebSocketCloseBadMessageFormat.0,
+        ),
+        Protocol(_) => (
+            C4ErrorDomain::WebSocketDomain,
+            C4NetworkErrorCode::kC4NumNetErrorCodesPlus1.0,
+        ),
+        Url(_) => (
+            C4ErrorDomain::NetworkDomain,
+            C4NetworkErrorCode::kC4NetErrInvalidURL.0,
+        ),
+        Http(ref resp) => (
+            C4ErrorDomain::WebSocketDomain,
+            resp.status().as_u16().into(),
+        ),
+        Tls(_) => (
+            C4ErrorDomain::NetworkDomain,
+            C4NetworkErrorCode::kC4NetErrTLSHandshakeFailed.0,
+        ),
+        _ => (
+            C4ErrorDomain::NetworkDomain,
+            C4NetworkErrorCode::kC4NumNetErrorCodesPlus1.0,
+        ),
+    };
+    c4_err(domain, code as c_int, &msg)
+}
@@ -8,7 +8,7 @@ use crate::{
         FLEncoder_EndDict, FLEncoder_Finish, FLEncoder_Free, FLEncoder_New, FLEncoder_WriteKey,
         FLEncoder_WriteString, FLError, FLSliceResult, FLTrust, FLValue_AsDict, FLValue_FromData,
     },
-    replicator::consts::*,
+    replicator::{consts::*, DEFAULT_CLOSE_TIMEOUT},
     value::ValueRef,
 };
 use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
@@ -34,8 +34,9 @@ use tokio::{
     runtime::Handle,
     sync::{mpsc, oneshot, Mutex as TokioMutex, Notify},
 };
+#[cfg(any(feature = "use-native-tls", feature = "use-rustls"))]
+use tokio_tungstenite::{connect_async_tls_with_config, Connector};
 use tokio_tungstenite::{
-    connect_async,
     tungstenite::{
         self,
         client::IntoClientRequest,
@@ -44,14 +45,55 @@ use tokio_tungstenite::{
         protocol::{frame::coding::CloseCode, CloseFrame},
         Message,
     },
-    WebSocketStream,
+    MaybeTlsStream, WebSocketStream,
 };
 
-pub fn c4socket_init(handle: Handle) {
-    let handle = Box::new(handle);
+/// Settings shared by all sockets created by factory
+pub(crate) struct SocketFactoryContext {
+    pub(crate) handle: Handle,
+    #[cfg(feature = "use-rustls")]
+    pub(crate) tls_config: Option<Arc<rustls::ClientConfig>>,
+}
+
+/// TLS settings of connection, without TLS support only `ws://` can be used
+#[cfg(any(feature = "use-native-tls", feature = "use-rustls"))]
+type TlsConnector = Option<Connector>;
+#[cfg(not(any(feature = "use-native-tls", feature = "use-rustls")))]
+type TlsConnector = ();
+
+impl SocketFactoryContext {
+    fn connector(&self) -> TlsConnector {
+        #[cfg(feature = "use-rustls")]
+        {
+            self.tls_config.clone().map(Connector::Rustls)
+        }
+        #[cfg(all(feature = "use-native-tls", not(feature = "use-rustls")))]
+        {
+            None
+        }
+    }
+}
+
+async fn connect(
+    request: Request,
+    connector: TlsConnector,
+) -> Result<(WsStream, Response), tungstenite::Error> {
+    #[cfg(any(feature = "use-native-tls", feature = "use-rustls"))]
+    {
+        connect_async_tls_with_config(request, None, false, connector).await
+    }
+    #[cfg(not(any(feature = "use-native-tls", feature = "use-rustls")))]
+    {
+        let () = connector;
+        tokio_tungstenite::connect_async(request).await
+    }
+}
+
+pub(crate) fn c4socket_init(ctx: SocketFactoryContext) {
+    let ctx = Box::new(ctx);
     let sock_factory = C4SocketFactory {
         framing: C4SocketFraming::kC4NoFraming,
-        context: Box::into_raw(handle) as *mut c_void,
+        context: Box::into_raw(ctx) as *mut c_void,
         open: Some(ws_open),
         write: Some(ws_write),
         completedReceive: Some(ws_completed_receive),
@@ -68,6 +110,7 @@ struct SocketImpl {
     writer: Arc<TokioMutex<(Option<WsWriter>, mpsc::UnboundedReceiver<Vec<u8>>)>>,
     send_queue: mpsc::UnboundedSender<Vec<u8>>,
     close_control: Arc<CloseControl>,
+    close_timeout: Duration,
 }
 
 struct ReadPushPull {
@@ -79,7 +122,10 @@ struct CloseControl {
     confirm: Notify,
     stop_read_loop: TokioMutex<Option<oneshot::Sender<()>>>,
     state: AtomicCloseState,
+    /// `c4socket_closed` was called
     signaled: AtomicBool,
+    /// `c4socket_gotHTTPResponse` was called
+    http_response_reported: AtomicBool,
 }
 
 #[repr(u8)]
@@ -110,12 +156,9 @@ impl AtomicCloseState {
     }
 }
 
-type WsReader =
-    SplitStream<WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>>;
-type WsWriter = futures_util::stream::SplitSink<
-    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>,
-    Message,
->;
+type WsStream = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+type WsReader = SplitStream<WsStream>;
+type WsWriter = futures_util::stream::SplitSink<WsStream, Message>;
 
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug)]
@@ -133,7 +176,9 @@ unsafe extern "C" fn ws_open(
     context: *mut c_void,
 ) {
     assert!(!context.is_null());
-    let handle: &Handle = &*(context as *mut Handle);
+    let ctx: &SocketFactoryContext = &*(context as *mut SocketFactoryContext);
+    let handle = &ctx.handle;
+    let connector = ctx.connector();
     assert!(!c4sock.is_null());
     let c4sock: &mut C4Socket = &mut *c4sock;
     assert!(c4Socket_getNativeHandle(c4sock).is_null());
@@ -141,6 +186,7 @@ unsafe extern "C" fn ws_open(
     let addr: &C4Address = &*addr;
 
     let request = c4address_to_request(c4sock as *mut C4Socket as usize, addr, options);
+    let close_timeout = close_timeout_from_options(options);
     info!(
         "c4sock {c4sock:?}: open was called with uri: {:?}",
         request.as_ref().map(Request::uri)
@@ -159,8 +205,10 @@ unsafe extern "C" fn ws_open(
             confirm: Notify::new(),
             stop_read_loop: TokioMutex::new(Some(stop_tx)),
             signaled: AtomicBool::new(false),
+            http_response_reported: AtomicBool::new(false),
         }),
         send_queue: send_q_tx,
+        close_timeout,
     });
     let read_push_pull = sock_impl.read_push_pull.clone();
     let writer = sock_impl.writer.clone();
@@ -173,6 +221,7 @@ unsafe extern "C" fn ws_open(
         match do_open(
             c4sock,
             request,
+            connector,
             stop_rx,
             read_push_pull,
             writer,
@@ -182,12 +231,7 @@ unsafe extern "C" fn ws_open(
         .await
         {
             Ok(()) => {}
-            Err(err) => {
-                trace!("c4sock {c4sock:?}: call c4socket_closed");
-                if !close_ctl.signaled.swap(true, Ordering::SeqCst) {
-                    c4socket_closed(c4sock.0, err.0);
-                }
-            }
+            Err(err) => close_ctl.signal_closed(c4sock, err.0),
         }
     });
 }
@@ -202,29 +246,56 @@ unsafe extern "C" fn ws_write(c4sock: *mut C4Socket, allocated_data: C4SliceResu
     assert!(!native.is_null());
     let socket: &SocketImpl = &*native;
     let writer = socket.writer.clone();
-    //TODO: change this when `Vec` allocator API was stabilized
-    // https://github.com/rust-lang/rust/issues/32838
+    //TODO: `Message::Binary` of tungstenite 0.23 owns `Vec<u8>`, so we can not
+    // pass ownership of `allocated_data` without copy. Since tungstenite 0.26
+    // `Message` uses `bytes::Bytes`, and `Bytes::from_owner` can wrap `C4SliceResult`,
+    // copy should be removed after upgrade (and measured, because tungstenite
+    // copies frame into own write buffer anyway).
+    // Another option is `Vec` allocator API https://github.com/rust-lang/rust/issues/32838
     let data: Vec<u8> = allocated_data.as_bytes().to_vec();
     socket
         .send_queue
         .send(data)
         .expect("Inernal error write to send queue failed");
+    let close_control = socket.close_control.clone();
     let c4sock = C4SocketPtr(c4sock);
     socket.handle.spawn(async move {
         let mut writer = writer.lock().await;
         let (writer, send_rx): &mut (Option<_>, mpsc::UnboundedReceiver<_>) = &mut writer;
-        if let (Some(writer), Ok(data)) = (writer.as_mut(), send_rx.try_recv()) {
-            send_binary_msg(c4sock, writer, data).await;
+        let Ok(data) = send_rx.try_recv() else {
+            return;
+        };
+        let ret = match writer.as_mut() {
+            Some(writer) => send_binary_msg(c4sock, writer, data).await,
+            None => {
+                error!("c4sock {c4sock:?}: write, but writer is None => connection gone");
+                Err(Error(c4error_make(
+                    C4ErrorDomain::NetworkDomain,
+                    C4NetworkErrorCode::kC4NetErrNotConnected.0,
+                    "write to not connected socket".into(),
+                )))
+            }
+        };
+        // core waits for completedWrite, so without this replicator stalls
+        if let Err(err) = ret {
+            close_control.signal_to_stop_read_loop(c4sock).await;
+            close_control.signal_closed(c4sock, err.0);
         }
     });
 }
 
-async fn send_binary_msg(ctx: C4SocketPtr, writer: &mut WsWriter, data: Vec<u8>) {
+async fn send_binary_msg(
+    ctx: C4SocketPtr,
+    writer: &mut WsWriter,
+    data: Vec<u8>,
+) -> Result<(), Error> {
     let n = data.len();
     if let Err(err) = writer.send(Message::Binary(data)).await {
         error!("c4sock {ctx:?}: writer.send failure: {err}");
+        Err(unsafe { tungstenite_err_to_c4_err(err) })
     } else {
         unsafe { c4socket_completedWrite(ctx.0, n) };
+        Ok(())
     }
 }
 
@@ -301,6 +372,7 @@ unsafe extern "C" fn ws_request_close(c4sock: *mut C4Socket, status: c_int, mess
     let socket: &SocketImpl = &*native;
     let writer = socket.writer.clone();
     let close_control = socket.close_control.clone();
+    let close_timeout = socket.close_timeout;
     let c4sock = C4SocketPtr(c4sock);
 
     let (wait_done, done_signal) = wait_signal();
@@ -330,7 +402,9 @@ unsafe extern "C" fn ws_request_close(c4sock: *mut C4Socket, status: c_int, mess
         let (writer, send_rx): &mut (Option<_>, mpsc::UnboundedReceiver<_>) = &mut writer;
         if let Some(writer) = writer.as_mut() {
             while let Ok(data) = send_rx.try_recv() {
-                send_binary_msg(c4sock, writer, data).await;
+                if send_binary_msg(c4sock, writer, data).await.is_err() {
+                    break;
+                }
             }
             trace!("c4sock {c4sock:?}: sending close message");
             if let Err(err) = writer
@@ -345,9 +419,7 @@ unsafe extern "C" fn ws_request_close(c4sock: *mut C4Socket, status: c_int, mess
             return;
         }
         if is_client_close {
-            // acording to comment from c4SocketTypes.h
-            const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
-            if tokio::time::timeout(CLOSE_TIMEOUT, close_control.confirm.notified())
+            if tokio::time::timeout(close_timeout, close_control.confirm.notified())
                 .await
                 .is_err()
             {
@@ -355,9 +427,7 @@ unsafe extern "C" fn ws_request_close(c4sock: *mut C4Socket, status: c_int, mess
                 close_control.signal_to_stop_read_loop(c4sock).await;
             }
         }
-        if !close_control.signaled.swap(true, Ordering::SeqCst) {
-            c4socket_closed(c4sock.0, err);
-        }
+        close_control.signal_closed(c4sock, err);
     });
     trace!(
         "c4sock {c4sock:?}: waiting done signal {:?}",
@@ -523,9 +593,23 @@ unsafe fn c4address_to_request(
     Ok(request)
 }
 
+/// Close timeout set via `ReplicatorParameters::with_close_timeout`
+unsafe fn close_timeout_from_options(options: C4Slice) -> Duration {
+    let options = FLValue_AsDict(FLValue_FromData(options, FLTrust::kFLUntrusted));
+    if options.is_null() {
+        return DEFAULT_CLOSE_TIMEOUT;
+    }
+    match ValueRef::new(FLDict_Get(options, kSocketOptionCloseTimeoutMs.into())) {
+        ValueRef::UnsignedInt(ms) => Duration::from_millis(ms),
+        ValueRef::SignedInt(ms) if ms >= 0 => Duration::from_millis(ms as u64),
+        _ => DEFAULT_CLOSE_TIMEOUT,
+    }
+}
+
 async fn do_open(
     c4sock: C4SocketPtr,
     request: Result<Request, Error>,
+    connector: TlsConnector,
     mut stop_rx: oneshot::Receiver<()>,
     read_push_pull: Arc<ReadPushPull>,
     writer: Arc<TokioMutex<(Option<WsWriter>, mpsc::UnboundedReceiver<Vec<u8>>)>>,
@@ -534,7 +618,7 @@ async fn do_open(
 ) -> Result<(), Error> {
     let request = request?;
     let (ws_stream, http_resp) = tokio::select! {
-        v = connect_async(request) => {
+        v = connect(request, connector) => {
             trace!("c4sock {c4sock:?}: connect_async finished");
             v.map_err(|err| unsafe { tungstenite_err_to_c4_err(err) })?
         }
@@ -552,13 +636,20 @@ async fn do_open(
 
     {
         let headers = unsafe { headers_to_dict(&http_resp) }?;
-        unsafe {
-            c4socket_gotHTTPResponse(
-                c4sock.0,
-                http_resp.status().as_u16() as c_int,
-                headers.as_fl_slice(),
-            )
-        };
+        if !close_control
+            .http_response_reported
+            .swap(true, Ordering::SeqCst)
+        {
+            unsafe {
+                c4socket_gotHTTPResponse(
+                    c4sock.0,
+                    http_resp.status().as_u16() as c_int,
+                    headers.as_fl_slice(),
+                )
+            };
+        } else {
+            warn!("c4sock {c4sock:?}: duplicate HTTP response, ignored");
+        }
         mem::drop(http_resp);
     }
     let (ws_writer, ws_reader) = ws_stream.split();
@@ -796,6 +887,16 @@ impl From<FLError> for Error {
 }
 
 impl CloseControl {
+    /// Core expects that `c4socket_closed` is called only once per socket,
+    /// so all paths should go through this function
+    unsafe fn signal_closed(&self, ctx: C4SocketPtr, err: C4Error) {
+        if !self.signaled.swap(true, Ordering::SeqCst) {
+            trace!("c4sock {:?}: call c4socket_closed", ctx.0);
+            c4socket_closed(ctx.0, err);
+        } else {
+            trace!("c4sock {:?}: c4socket_closed already called", ctx.0);
+        }
+    }
     async fn signal_to_stop_read_loop(&self, ctx: C4SocketPtr) {
         let mut lock = self.stop_read_loop.lock().await;
         if let Some(stop_tx) = lock.take() {
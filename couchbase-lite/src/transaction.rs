@@ -3,14 +3,16 @@ use crate::{
     error::{c4error_init, Error, Result},
     ffi::{
         c4db_beginTransaction, c4db_endTransaction, c4db_getSharedFleeceEncoder, c4db_purgeDoc,
-        c4doc_put, c4doc_update, C4DocPutRequest, C4ErrorCode, C4ErrorDomain, C4RevisionFlags,
-        FLSlice, FLSliceResult,
+        c4doc_put, c4doc_update, C4DocContentLevel, C4DocPutRequest, C4ErrorCode, C4ErrorDomain,
+        C4RevisionFlags, C4SequenceNumber, FLSlice, FLSliceResult, _FLEncoder,
     },
     Database,
 };
 use log::error;
-use serde_fleece::FlEncoderSession;
+use serde::Serialize;
+use serde_fleece::{to_fl_slice_result_with_encoder, FlEncoderSession};
 use std::{
+    borrow::Borrow,
     ops::Deref,
     ptr::{self, NonNull},
 };
@@ -18,6 +20,7 @@ use std::{
 pub struct Transaction<'db> {
     db: &'db Database,
     finished: bool,
+    encoder: Option<FlEncoderSession>,
 }
 
 impl Transaction<'_> {
@@ -27,6 +30,7 @@ impl Transaction<'_> {
             Ok(Transaction {
                 db,
                 finished: false,
+                encoder: None,
             })
         } else {
             Err(c4err.into())
@@ -38,6 +42,13 @@ impl Transaction<'_> {
         self.end_transaction(true)
     }
 
+    /// Roll back all changes made in transaction, the same happens
+    /// on drop, but this way it is explicit and error is returned to caller
+    #[inline]
+    pub fn abort(mut self) -> Result<()> {
+        self.end_transaction(false)
+    }
+
     fn end_transaction(&mut self, commit: bool) -> Result<()> {
         self.finished = true;
         let mut c4err = c4error_init();
@@ -53,11 +64,147 @@ impl Transaction<'_> {
         self.main_save(doc, false)
     }
 
+    /// The same as `Transaction::save`, but returns the sequence number
+    /// assigned to the saved revision, so there is no need to reload document
+    pub fn save_returning_sequence(&mut self, doc: &mut Document) -> Result<C4SequenceNumber> {
+        self.main_save(doc, false)?;
+        doc.sequence().ok_or_else(|| {
+            Error::LogicError(format!(
+                "Document {} have no underlying C4Document after save",
+                doc.id()
+            ))
+        })
+    }
+
+    /// Encode and save several documents using one encoder session,
+    /// `None` as document id means generate new id.
+    /// Returns ids of saved documents
+    pub fn save_all<'a, T, I>(&mut self, docs: I) -> Result<Vec<String>>
+    where
+        T: Serialize + 'a,
+        I: IntoIterator<Item = (Option<&'a str>, &'a T)>,
+    {
+        let session = self.shared_encoder_session()?;
+        let enc: NonNull<_FLEncoder> = *session.borrow();
+        let docs = docs.into_iter();
+        let mut ids = Vec::with_capacity(docs.size_hint().0);
+        for (doc_id, data) in docs {
+            let body = to_fl_slice_result_with_encoder(data, enc)?;
+            let doc_id = doc_id
+                .map(str::to_string)
+                .unwrap_or_else(Document::generate_id);
+            let mut doc = Document::new_with_id_fleece(doc_id, body);
+            self.main_save(&mut doc, false)?;
+            ids.push(doc.id().to_string());
+        }
+        Ok(ids)
+    }
+
+    /// Convert JSON text to fleece and save it as document body,
+    /// `None` as document id means generate new id.
+    /// Returns id of saved document
+    pub fn save_json(&mut self, doc_id: Option<&str>, json: &str) -> Result<String> {
+        let doc_id = doc_id
+            .map(str::to_string)
+            .unwrap_or_else(Document::generate_id);
+        let enc = self.shared_encoder_session()?;
+        let mut doc = Document::from_json_str(doc_id, json, enc)?;
+        self.main_save(&mut doc, false)?;
+        Ok(doc.id().to_string())
+    }
+
     #[inline]
     pub fn delete(&mut self, doc: &mut Document) -> Result<()> {
         self.main_save(doc, true)
     }
 
+    /// Creates a deletion revision for document with `doc_id` without loading its body.
+    /// Returns `true` if a live (not deleted) document existed.
+    pub fn delete_by_id(&mut self, doc_id: &str) -> Result<bool> {
+        let existing =
+            self.db
+                .do_internal_get_opt(doc_id, true, C4DocContentLevel::kDocGetMetadata)?;
+        if existing.as_ref().map(|x| x.is_deleted()).unwrap_or(false) {
+            return Ok(false);
+        }
+        let parent_rev_id: Option<FLSlice> = existing.as_ref().map(|x| x.revision_id().into());
+        let rq = C4DocPutRequest {
+            body: FLSlice::default(),
+            docID: doc_id.into(),
+            revFlags: C4RevisionFlags::kRevDeleted,
+            existingRevision: false,
+            allowConflict: false,
+            history: parent_rev_id
+                .as_ref()
+                .map(|x| x as *const FLSlice)
+                .unwrap_or(ptr::null()),
+            historyCount: usize::from(parent_rev_id.is_some()),
+            save: true,
+            maxRevTreeDepth: 0,
+            remoteDBID: 0,
+            allocedBody: FLSliceResult::default(),
+            deltaCB: None,
+            deltaCBContext: ptr::null_mut(),
+            deltaSourceRevID: FLSlice::default(),
+        };
+        let mut c4err = c4error_init();
+        let new_doc =
+            unsafe { c4doc_put(self.db.inner.0.as_ptr(), &rq, ptr::null_mut(), &mut c4err) };
+        match NonNull::new(new_doc) {
+            Some(new_doc) => {
+                drop(C4DocumentOwner(new_doc));
+                Ok(existing.is_some())
+            }
+            None => Err(c4err.into()),
+        }
+    }
+
+    /// Inserts revision with already known revision history, for example during import
+    /// from other storage, so the document later replicates without conflicts.
+    /// `history` starts with ID of the new revision, followed by its ancestors
+    /// (the parent first), `body` should be encoded with database's shared encoder.
+    /// It is an error if the new revision conflicts with existing revisions of the document.
+    pub fn put_revision(
+        &mut self,
+        doc_id: &str,
+        history: &[&str],
+        body: FLSliceResult,
+        flags: C4RevisionFlags,
+    ) -> Result<()> {
+        if history.is_empty() {
+            return Err(Error::LogicError(format!(
+                "put_revision: empty history for document {doc_id}"
+            )));
+        }
+        let history: Vec<FLSlice> = history.iter().map(|rev_id| (*rev_id).into()).collect();
+        let rq = C4DocPutRequest {
+            body: body.as_fl_slice(),
+            docID: doc_id.into(),
+            revFlags: flags,
+            existingRevision: true,
+            allowConflict: false,
+            history: history.as_ptr(),
+            historyCount: history.len(),
+            save: true,
+            maxRevTreeDepth: 0,
+            remoteDBID: 0,
+            allocedBody: FLSliceResult::default(),
+            deltaCB: None,
+            deltaCBContext: ptr::null_mut(),
+            deltaSourceRevID: FLSlice::default(),
+        };
+        let mut c4err = c4error_init();
+        let new_doc =
+            unsafe { c4doc_put(self.db.inner.0.as_ptr(), &rq, ptr::null_mut(), &mut c4err) };
+        match NonNull::new(new_doc) {
+            Some(new_doc) => {
+                drop(C4DocumentOwner(new_doc));
+                Ok(())
+            }
+            None => Err(c4err.into()),
+        }
+    }
+
     /// Removes all trace of a document and its revisions from the database.
     #[inline]
     pub fn purge_by_id(&mut self, doc_id: &str) -> Result<()> {
@@ -69,6 +216,34 @@ impl Transaction<'_> {
         }
     }
 
+    /// Like `purge_by_id`, but returns `false` instead of error
+    /// if there is no document with such id
+    pub fn try_purge_by_id(&mut self, doc_id: &str) -> Result<bool> {
+        let mut c4err = c4error_init();
+        if unsafe { c4db_purgeDoc(self.db.inner.0.as_ptr(), doc_id.into(), &mut c4err) } {
+            Ok(true)
+        } else if c4err.domain == C4ErrorDomain::LiteCoreDomain
+            && c4err.code == C4ErrorCode::kC4ErrorNotFound.0
+        {
+            Ok(false)
+        } else {
+            Err(c4err.into())
+        }
+    }
+
+    /// Purges several documents, returns how many of them actually existed.
+    /// Absent ids are skipped, on any other error purging stops
+    /// and the error is returned, so the transaction will be rolled back on drop.
+    pub fn purge_by_ids(&mut self, ids: &[&str]) -> Result<usize> {
+        let mut purged = 0;
+        for doc_id in ids {
+            if self.try_purge_by_id(doc_id)? {
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
     /// Get shared "fleece" encoder, `&mut self` to make possible
     /// exists only one session
     #[inline]
@@ -81,6 +256,18 @@ impl Transaction<'_> {
             .map(FlEncoderSession::new)
     }
 
+    /// Shared "fleece" encoder session that lives as long as transaction,
+    /// so it can be lent to several `Document::new_with_encoder` calls.
+    /// Encoder is ready for the next document after every encoding,
+    /// even failed one
+    pub fn encoder(&mut self) -> Result<&mut FlEncoderSession> {
+        let enc = match self.encoder.take() {
+            Some(enc) => enc,
+            None => self.shared_encoder_session()?,
+        };
+        Ok(self.encoder.insert(enc))
+    }
+
     fn main_save(&mut self, doc: &mut Document, deletion: bool) -> Result<()> {
         let mut retrying = false;
         let mut saving_doc = None;
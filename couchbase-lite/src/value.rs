@@ -1,13 +1,18 @@
 use crate::{
     error::{Error, Result},
     ffi::{
-        FLArray, FLArray_Count, FLArray_Get, FLArray_IsEmpty, FLDict, FLDict_Count, FLDict_Get,
-        FLDict_IsEmpty, FLSlice, FLValue, FLValueType, FLValue_AsArray, FLValue_AsBool,
+        FLArray, FLArray_Count, FLArray_Get, FLArray_IsEmpty, FLDict, FLDictIterator,
+        FLDictIterator_Begin, FLDictIterator_End, FLDictIterator_GetKeyString,
+        FLDictIterator_GetValue, FLDictIterator_Next, FLDict_Count, FLDict_Get, FLDict_IsEmpty,
+        FLSlice, FLValue, FLValueType, FLValue_AsArray, FLValue_AsBool, FLValue_AsData,
         FLValue_AsDict, FLValue_AsDouble, FLValue_AsFloat, FLValue_AsInt, FLValue_AsString,
         FLValue_AsUnsigned, FLValue_GetType, FLValue_IsDouble, FLValue_IsInteger,
-        FLValue_IsUnsigned,
+        FLValue_IsUnsigned, _FLDict,
     },
 };
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_fleece::NonNullConst;
+use std::{borrow::Borrow, mem::MaybeUninit};
 
 #[derive(Debug, Clone, Copy)]
 pub enum ValueRef<'a> {
@@ -18,6 +23,7 @@ pub enum ValueRef<'a> {
     Float(f32),
     Double(f64),
     String(&'a str),
+    Data(&'a [u8]),
     Array(ValueRefArray),
     Dict(ValueRefDict),
 }
@@ -55,6 +61,15 @@ impl ValueRef<'_> {
     pub fn is_null(&self) -> bool {
         matches!(self, ValueRef::Null)
     }
+    /// Wrap `FLValue` obtained via FFI
+    ///
+    /// # Safety
+    /// `value` should be null or point to valid fleece value,
+    /// that lives as long as result is used
+    #[inline]
+    pub unsafe fn from_fl_value(value: FLValue) -> Self {
+        Self::new(value)
+    }
     pub(crate) unsafe fn new(value: FLValue) -> Self {
         use FLValueType::*;
         match FLValue_GetType(value) {
@@ -79,7 +94,7 @@ impl ValueRef<'_> {
             }
             kFLArray => ValueRef::Array(ValueRefArray(FLValue_AsArray(value))),
             kFLDict => ValueRef::Dict(ValueRefDict(FLValue_AsDict(value))),
-            kFLData => unimplemented!(),
+            kFLData => ValueRef::Data(FLValue_AsData(value).into()),
         }
     }
 }
@@ -126,6 +141,97 @@ impl ValueRefDict {
     pub fn get(&self, key: FLSlice) -> ValueRef {
         unsafe { ValueRef::new(self.get_raw(key)) }
     }
+    /// Walk nested dictionaries by path like "a.b.c",
+    /// returns `ValueRef::Null` if any part of path is missing
+    /// or is not dictionary
+    pub fn get_path(&self, path: &str) -> ValueRef {
+        let mut dict = *self;
+        let mut parts = path.split('.').peekable();
+        while let Some(key) = parts.next() {
+            let value = unsafe { ValueRef::new(dict.get_raw(key.as_bytes().into())) };
+            if parts.peek().is_none() {
+                return value;
+            }
+            match value {
+                ValueRef::Dict(x) => dict = x,
+                _ => break,
+            }
+        }
+        ValueRef::Null
+    }
+    /// View dictionary as `serde_fleece::Dict`
+    #[inline]
+    pub fn as_fleece_dict(&self) -> Option<serde_fleece::Dict<'_>> {
+        serde_fleece::Dict::new(&self.0)
+    }
+}
+
+impl From<serde_fleece::Dict<'_>> for ValueRefDict {
+    #[inline]
+    fn from(dict: serde_fleece::Dict) -> Self {
+        let inner: &NonNullConst<_FLDict> = dict.borrow();
+        ValueRefDict(inner.as_ptr())
+    }
+}
+
+impl Serialize for ValueRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match *self {
+            ValueRef::Null => serializer.serialize_unit(),
+            ValueRef::Bool(x) => serializer.serialize_bool(x),
+            ValueRef::SignedInt(x) => serializer.serialize_i64(x),
+            ValueRef::UnsignedInt(x) => serializer.serialize_u64(x),
+            ValueRef::Float(x) => serializer.serialize_f32(x),
+            ValueRef::Double(x) => serializer.serialize_f64(x),
+            ValueRef::String(x) => serializer.serialize_str(x),
+            ValueRef::Data(x) => serializer.serialize_bytes(x),
+            ValueRef::Array(ref x) => x.serialize(serializer),
+            ValueRef::Dict(ref x) => x.serialize(serializer),
+        }
+    }
+}
+
+impl Serialize for ValueRefArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let n = self.len();
+        let mut seq = serializer.serialize_seq(Some(n as usize))?;
+        for i in 0..n {
+            seq.serialize_element(&self.get(i))?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for ValueRefDict {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len() as usize))?;
+        let mut it = MaybeUninit::<FLDictIterator>::uninit();
+        let mut it = unsafe {
+            FLDictIterator_Begin(self.0, it.as_mut_ptr());
+            it.assume_init()
+        };
+        let mut ret = Ok(());
+        while !unsafe { FLDictIterator_GetValue(&it) }.is_null() {
+            let key: &str = match unsafe { FLDictIterator_GetKeyString(&it) }.try_into() {
+                Ok(x) => x,
+                Err(_) => {
+                    ret = Err(ser::Error::custom("dictionary key is not valid UTF-8"));
+                    break;
+                }
+            };
+            let value = unsafe { ValueRef::new(FLDictIterator_GetValue(&it)) };
+            if let Err(err) = map.serialize_entry(key, &value) {
+                ret = Err(err);
+                break;
+            }
+            if !unsafe { FLDictIterator_Next(&mut it) } {
+                break;
+            }
+        }
+        unsafe { FLDictIterator_End(&mut it) };
+        ret?;
+        map.end()
+    }
 }
 
 pub trait FromValueRef<'a>: Sized {
@@ -3,7 +3,12 @@ mod need_sync_gateway;
 
 use couchbase_lite::{ffi::FLSlice, *};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs, str, time::Instant};
+use std::{
+    collections::HashMap,
+    fs, str,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
 use tempfile::tempdir;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -54,10 +59,23 @@ fn test_write_read() {
         }
         assert_eq!(ids_and_data.len() as u64, db.document_count());
         for (doc_id, foo) in &ids_and_data {
+            assert!(db.document_exists(doc_id).unwrap());
+            let meta = db
+                .get_existing_with_level(doc_id, ContentLevel::Metadata)
+                .unwrap();
             let doc = db.get_existing(doc_id).unwrap();
+            assert_eq!(meta.sequence(), doc.sequence());
+            assert_eq!(meta.revision_id(), doc.revision_id());
             let loaded_foo: Foo = doc.decode_body().unwrap();
             assert_eq!(*foo, loaded_foo);
         }
+        assert!(!db.document_exists("not_existing_doc").unwrap());
+        let (doc_id, foo) = &ids_and_data[0];
+        let seq = db.get_existing(doc_id).unwrap().sequence().unwrap();
+        let doc = db.get_doc_by_sequence(seq).unwrap().unwrap();
+        assert_eq!(doc_id, doc.id());
+        assert_eq!(*foo, doc.decode_body::<Foo>().unwrap());
+        assert!(db.get_doc_by_sequence(1_000_000).unwrap().is_none());
     }
 
     println!("Close and reopen");
@@ -112,6 +130,10 @@ fn test_write_read() {
                 let (doc_id, foo) = ids_and_data_iter.next().unwrap();
                 assert_eq!(doc_id, doc.id());
                 assert_eq!(doc_id, doc_info.doc_id());
+                assert_eq!(Some(doc_info.sequence()), doc.sequence());
+                assert_eq!(doc.revision_id(), Some(doc_info.revision_id()));
+                assert!(doc_info.flags().contains(DocumentFlags::EXISTS));
+                assert!(!doc_info.flags().contains(DocumentFlags::DELETED));
                 let loaded_foo: Foo = doc.decode_body().unwrap();
                 assert_eq!(
                     Foo {
@@ -133,6 +155,7 @@ fn test_write_read() {
             }
             trans.commit().unwrap();
         }
+        assert!(!db.document_exists(&ids_and_data[0].0).unwrap());
         let rest = ids_and_data.len() - n;
         {
             println!("count result after delete");
@@ -151,12 +174,43 @@ fn test_write_read() {
                 .count()
                 .unwrap()
             );
+            assert_eq!(
+                ids_and_data.len() as u64,
+                db.document_count_with_flags(
+                    DocEnumeratorFlags::INCLUDE_DELETED
+                        | DocEnumeratorFlags::INCLUDE_NON_CONFLICTED
+                )
+                .unwrap()
+            );
+            assert_eq!(
+                rest as u64,
+                db.document_count_with_flags(DocEnumeratorFlags::default())
+                    .unwrap()
+            );
+            assert_eq!(
+                0,
+                db.document_count_with_flags(DocEnumeratorFlags::INCLUDE_DELETED)
+                    .unwrap()
+            );
+            assert_eq!(
+                rest as u64,
+                db.collection_document_count(ffi::kC4DefaultCollectionSpec, false)
+                    .unwrap()
+            );
+            assert_eq!(
+                ids_and_data.len() as u64,
+                db.collection_document_count(ffi::kC4DefaultCollectionSpec, true)
+                    .unwrap()
+            );
         }
         {
             let mut trans = db.transaction().unwrap();
             for doc_id in ids_and_data.iter().take(n).map(|x| x.0.as_str()) {
                 trans.purge_by_id(doc_id).unwrap();
             }
+            let purged_ids: Vec<&str> = ids_and_data.iter().take(n).map(|x| x.0.as_str()).collect();
+            assert_eq!(0, trans.purge_by_ids(&purged_ids).unwrap());
+            assert!(!trans.try_purge_by_id(purged_ids[0]).unwrap());
             trans.commit().unwrap();
         }
         {
@@ -176,12 +230,148 @@ fn test_write_read() {
                 .count()
                 .unwrap()
             );
+            assert_eq!(
+                rest as u64,
+                db.document_count_with_flags(
+                    DocEnumeratorFlags::INCLUDE_DELETED
+                        | DocEnumeratorFlags::INCLUDE_NON_CONFLICTED
+                )
+                .unwrap()
+            );
+        }
+        {
+            let existing_ids: Vec<&str> = ids_and_data
+                .iter()
+                .skip(n)
+                .take(2)
+                .map(|x| x.0.as_str())
+                .chain(std::iter::once("not-existing-doc-id"))
+                .collect();
+            let mut trans = db.transaction().unwrap();
+            assert_eq!(2, trans.purge_by_ids(&existing_ids).unwrap());
+            trans.commit().unwrap();
+            assert_eq!((rest - 2) as u64, db.document_count());
+            assert!(!db.document_exists(existing_ids[0]).unwrap());
         }
     }
 
     tmp_dir.close().expect("Can not close tmp_dir");
 }
 
+#[test]
+fn test_transaction_encoder() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    println!("we create tempdir at {}", tmp_dir.path().display());
+    {
+        let mut db =
+            Database::open_with_flags(&tmp_dir.path().join("a.cblite2"), DatabaseFlags::CREATE)
+                .unwrap();
+        let mut ids_and_data = Vec::<(String, Foo)>::new();
+        {
+            let mut trans = db.transaction().unwrap();
+            for i in 0..100 {
+                let foo = Foo {
+                    i,
+                    s: format!("Hello {}", i),
+                };
+                let mut doc = Document::new_with_encoder(&foo, trans.encoder().unwrap()).unwrap();
+                trans.save(&mut doc).unwrap();
+                ids_and_data.push((doc.id().into(), foo));
+                if i == 50 {
+                    // failed encoding doesn't break the next one
+                    let enc = trans.encoder().unwrap();
+                    assert!(Document::from_json_str_with_encoder("bad", "{not json", enc).is_err());
+                }
+            }
+            trans.commit().unwrap();
+        }
+        assert_eq!(ids_and_data.len() as u64, db.document_count());
+        for (doc_id, foo) in &ids_and_data {
+            let loaded_foo: Foo = db.get_existing(doc_id).unwrap().decode_body().unwrap();
+            assert_eq!(*foo, loaded_foo);
+        }
+    }
+    tmp_dir.close().expect("Can not close tmp_dir");
+}
+
+#[test]
+fn test_open_in_memory() {
+    let _ = env_logger::try_init();
+    {
+        let mut db = Database::open_in_memory("a").unwrap();
+        let mut trans = db.transaction().unwrap();
+        let foo = Foo {
+            i: 17,
+            s: "hello".into(),
+        };
+        let enc = trans.shared_encoder_session().unwrap();
+        let mut doc = Document::new(&foo, enc).unwrap();
+        trans.save(&mut doc).unwrap();
+        trans.commit().unwrap();
+        assert_eq!(1, db.document_count());
+
+        let mut trans = db.transaction().unwrap();
+        let enc = trans.shared_encoder_session().unwrap();
+        let mut doc = Document::new(&foo, enc).unwrap();
+        trans.save(&mut doc).unwrap();
+        trans.abort().unwrap();
+        assert_eq!(1, db.document_count());
+    }
+    let db = Database::open_in_memory("a").unwrap();
+    assert_eq!(0, db.document_count());
+}
+
+#[test]
+fn test_copy_database() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    let src_path = tmp_dir.path().join("src.cblite2");
+    let doc_id = {
+        let mut db = Database::open_with_flags(&src_path, DatabaseFlags::CREATE).unwrap();
+        let mut trans = db.transaction().unwrap();
+        let foo = Foo {
+            i: 17,
+            s: "hello".into(),
+        };
+        let enc = trans.shared_encoder_session().unwrap();
+        let mut doc = Document::new(&foo, enc).unwrap();
+        trans.save(&mut doc).unwrap();
+        trans.commit().unwrap();
+        doc.id().to_string()
+    };
+
+    let dst_dir = tmp_dir.path().join("dst");
+    fs::create_dir_all(&dst_dir).unwrap();
+    Database::copy(
+        &src_path,
+        "dst",
+        DatabaseConfig::new(&dst_dir, DatabaseFlags::CREATE),
+    )
+    .unwrap();
+    assert!(Database::copy(
+        &src_path,
+        "dst",
+        DatabaseConfig::new(&dst_dir, DatabaseFlags::CREATE),
+    )
+    .is_err());
+
+    let db =
+        Database::open_named("dst", DatabaseConfig::new(&dst_dir, DatabaseFlags::empty())).unwrap();
+    assert_eq!(1, db.document_count());
+    let foo: Foo = db.get_existing(&doc_id).unwrap().decode_body().unwrap();
+    assert_eq!(17, foo.i);
+    assert!(!db.flags().contains(DatabaseFlags::READ_ONLY));
+    drop(db);
+
+    let db = Database::open_named(
+        "dst",
+        DatabaseConfig::new(&dst_dir, DatabaseFlags::READ_ONLY),
+    )
+    .unwrap();
+    assert!(db.flags().contains(DatabaseFlags::READ_ONLY));
+}
+
 #[test]
 fn test_observed_changes() {
     let _ = env_logger::try_init();
@@ -213,6 +403,14 @@ fn test_observed_changes() {
         assert!(!changes[0].revision_id().unwrap().is_empty());
         assert!(!changes[0].external());
         assert!(changes[0].body_size() > 2);
+        assert_eq!(1, changes[0].generation());
+        assert!(!changes[0].is_conflict());
+        assert!(changes[0].sequence() > 0);
+        let doc = changes[0].get_doc(&db).unwrap().unwrap();
+        assert_eq!(doc_id, doc.id());
+        assert_eq!(Some(changes[0].sequence()), doc.sequence());
+        assert_eq!(Some(changes[0].revision_id().unwrap()), doc.revision_id());
+        drop(doc);
 
         let changes: Vec<_> = db.observed_changes().collect();
         assert!(changes.is_empty());
@@ -233,7 +431,28 @@ fn test_observed_changes() {
 
         let doc = db.get_existing(&doc_id).unwrap();
         println!("doc {:?}", doc);
+        assert!(doc.is_deleted());
+        assert!(!doc.is_conflicted());
+        assert!(!doc.has_attachments());
         doc.decode_body::<Empty>().unwrap();
+
+        {
+            let mut trans = db.transaction().unwrap();
+            for i in 0..5 {
+                let foo = Foo {
+                    i,
+                    s: "batch".into(),
+                };
+                let enc = trans.shared_encoder_session().unwrap();
+                let mut doc = Document::new(&foo, enc).unwrap();
+                trans.save(&mut doc).unwrap();
+            }
+            trans.commit().unwrap();
+        }
+        let changes: Vec<_> = db.observed_changes_with_batch_size(2).collect();
+        assert_eq!(5, changes.len());
+        let changes: Vec<_> = db.observed_changes().collect();
+        assert!(changes.is_empty());
     }
     tmp_dir.close().expect("Can not close tmp_dir");
 }
@@ -259,12 +478,69 @@ fn test_save_float() {
         drop(doc);
 
         let doc = db.get_existing(&doc_id).unwrap();
+        #[derive(Deserialize)]
+        struct BorrowedS<'a> {
+            s: &'a str,
+        }
+        let borrowed: BorrowedS = doc.decode_body_borrowed().unwrap();
+        assert_eq!(s.s, borrowed.s);
         let loaded_s: S = doc.decode_body().unwrap();
         assert_eq!(s, loaded_s);
     }
     tmp_dir.close().expect("Can not close tmp_dir");
 }
 
+#[test]
+fn test_document_from_json() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    println!("we create tempdir at {}", tmp_dir.path().display());
+    let db_path = tmp_dir.path().join("a.cblite2");
+    {
+        let mut db = Database::open_with_flags(&db_path, DatabaseFlags::CREATE).unwrap();
+        let mut trans = db.transaction().unwrap();
+        let enc = trans.shared_encoder_session().unwrap();
+        let mut doc =
+            Document::from_json_str("json_doc", r#"{"type": "S", "f": 17.48, "s": "ABCD"}"#, enc)
+                .unwrap();
+        trans.save(&mut doc).unwrap();
+        trans.commit().unwrap();
+
+        let doc = db.get_existing("json_doc").unwrap();
+        let loaded_s: S = doc.decode_body().unwrap();
+        assert_eq!(
+            S {
+                f: 17.48,
+                s: "ABCD".into()
+            },
+            loaded_s
+        );
+
+        let mut trans = db.transaction().unwrap();
+        let id = trans
+            .save_json(Some("json_doc2"), r#"{"type": "S", "f": 1.5, "s": "EFG"}"#)
+            .unwrap();
+        assert_eq!("json_doc2", id);
+        let gen_id = trans
+            .save_json(None, r#"{"type": "S", "f": 2.5, "s": "HIJ"}"#)
+            .unwrap();
+        assert!(trans.save_json(None, "{not json").is_err());
+        trans.commit().unwrap();
+
+        let loaded_s: S = db.get_existing(&id).unwrap().decode_body().unwrap();
+        assert_eq!(
+            S {
+                f: 1.5,
+                s: "EFG".into()
+            },
+            loaded_s
+        );
+        let loaded_s: S = db.get_existing(&gen_id).unwrap().decode_body().unwrap();
+        assert_eq!("HIJ", loaded_s.s);
+    }
+    tmp_dir.close().expect("Can not close tmp_dir");
+}
+
 #[test]
 fn test_save_several_times() {
     fn create_s(i: i32) -> S {
@@ -296,12 +572,14 @@ fn test_save_several_times() {
             Document::new_with_id(doc_id.as_str(), &s, db.shared_encoder_session().unwrap())
                 .unwrap();
         let mut trans = db.transaction().unwrap();
-        trans.save(&mut doc).unwrap();
+        let seq = trans.save_returning_sequence(&mut doc).unwrap();
         trans.commit().unwrap();
+        assert_eq!(Some(seq), doc.sequence());
         drop(doc);
         assert_eq!(1, db.document_count());
 
         let doc = db.get_existing(&doc_id).unwrap();
+        assert_eq!(Some(seq), doc.sequence());
         assert_eq!(s, doc.decode_body::<S>().unwrap());
 
         let s = create_s(400);
@@ -315,12 +593,74 @@ fn test_save_several_times() {
         drop(doc);
         assert_eq!(1, db.document_count());
 
+        let mut doc = db.get_existing(&doc_id).unwrap();
+        assert_eq!(s, doc.decode_body_borrowed::<S>().unwrap());
+        assert_eq!(
+            s,
+            serde_fleece::from_slice::<S>(doc.body_fleece().unwrap()).unwrap()
+        );
+        let history = doc.revision_history().unwrap();
+        assert_eq!(3, history.len());
+        assert_eq!(doc.revision_id(), Some(history[0].as_str()));
+        let cur_seq = doc.sequence();
+        doc.select_revision(&history[1]).unwrap();
+        assert!(doc.sequence() < cur_seq);
+        doc.select_current_revision();
+        assert_eq!(cur_seq, doc.sequence());
+
+        let s = create_s(401);
+        let fleece_data =
+            serde_fleece::to_fl_slice_result_with_encoder(&s, db.shared_encoder_session().unwrap())
+                .unwrap();
+        doc.update_body_fleece(fleece_data);
+        let mut trans = db.transaction().unwrap();
+        trans.save(&mut doc).unwrap();
+        trans.commit().unwrap();
+        drop(doc);
+        assert_eq!(1, db.document_count());
+
         let doc = db.get_existing(&doc_id).unwrap();
         assert_eq!(s, doc.decode_body::<S>().unwrap());
     }
     tmp_dir.close().expect("Can not close tmp_dir");
 }
 
+#[test]
+fn test_put_revision() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    println!("we create tempdir at {}", tmp_dir.path().display());
+    let db_path = tmp_dir.path().join("a.cblite2");
+    {
+        let mut db = Database::open_with_flags(&db_path, DatabaseFlags::CREATE).unwrap();
+        let foo = Foo {
+            i: 17,
+            s: "imported".into(),
+        };
+        let history = [
+            "2-bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb",
+            "1-aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+        ];
+        let mut trans = db.transaction().unwrap();
+        let body = serde_fleece::to_fl_slice_result_with_encoder(
+            &foo,
+            trans.shared_encoder_session().unwrap(),
+        )
+        .unwrap();
+        trans
+            .put_revision("imported", &history, body, ffi::C4RevisionFlags(0))
+            .unwrap();
+        trans.commit().unwrap();
+
+        let doc = db.get_existing("imported").unwrap();
+        assert_eq!(Some(history[0]), doc.revision_id());
+        assert_eq!(2, doc.generation());
+        assert_eq!(history.to_vec(), doc.revision_history().unwrap());
+        assert_eq!(foo, doc.decode_body::<Foo>().unwrap());
+    }
+    tmp_dir.close().expect("Can not close tmp_dir");
+}
+
 #[test]
 fn test_indices() {
     let _ = env_logger::try_init();
@@ -348,18 +688,34 @@ fn test_indices() {
             .unwrap();
         println!("after index creation:");
         assert_eq!(vec!["Foo_s".to_string()], get_index_list(&db));
+        {
+            let mut index_it = db.get_indexes().unwrap();
+            let index = index_it.next().unwrap().unwrap();
+            assert_eq!(IndexType::ValueIndex, index.index_type().unwrap());
+            assert_eq!("[[\".s\"]]", index.expr_as_str().unwrap());
+        }
+        db.create_index_with_language(
+            "Foo_i",
+            "i",
+            QueryLanguage::kC4N1QLQuery,
+            IndexType::ValueIndex,
+            None,
+        )
+        .unwrap();
+        let mut index_list = get_index_list(&db);
+        index_list.sort();
+        assert_eq!(vec!["Foo_i".to_string(), "Foo_s".to_string()], index_list);
 
         {
-            let mut trans = db.transaction().unwrap();
-            for i in 0..10_000 {
-                let foo = Foo {
-                    i: i,
+            let foos: Vec<Foo> = (0..10_000)
+                .map(|i| Foo {
+                    i,
                     s: format!("Hello {}", i),
-                };
-                let enc = trans.shared_encoder_session().unwrap();
-                let mut doc = Document::new(&foo, enc).unwrap();
-                trans.save(&mut doc).unwrap();
-            }
+                })
+                .collect();
+            let mut trans = db.transaction().unwrap();
+            let ids = trans.save_all(foos.iter().map(|foo| (None, foo))).unwrap();
+            assert_eq!(foos.len(), ids.len());
             trans.commit().unwrap();
         }
 
@@ -497,6 +853,102 @@ fn test_like_offset_limit() {
     }
 }
 
+#[test]
+fn test_array_index() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    println!("we create tempdir at {}", tmp_dir.path().display());
+    let db_path = tmp_dir.path().join("a.cblite2");
+    {
+        let mut db = Database::open_with_flags(&db_path, DatabaseFlags::CREATE).unwrap();
+        let mut trans = db.transaction().unwrap();
+        trans
+            .save_json(Some("a"), r#"{"tags": ["red", "green"]}"#)
+            .unwrap();
+        trans.save_json(Some("b"), r#"{"tags": ["blue"]}"#).unwrap();
+        trans.save_json(Some("c"), r#"{"tags": ["red"]}"#).unwrap();
+        trans.commit().unwrap();
+
+        let opts = || IndexOptions {
+            unnest_path: Some("tags"),
+            ..Default::default()
+        };
+        db.create_index("tags", "[]", IndexType::ArrayIndex, Some(opts()))
+            .unwrap();
+        assert!(db
+            .create_index("bad", "[]", IndexType::ValueIndex, Some(opts()))
+            .is_err());
+        assert!(db
+            .create_index("pred", r#"[[".tags"]]"#, IndexType::PredictiveIndex, None)
+            .is_err());
+        let mut index_types = vec![];
+        let mut it = db.get_indexes().unwrap();
+        while let Some(info) = it.next().unwrap() {
+            index_types.push((
+                info.name_as_str().unwrap().to_string(),
+                info.index_type().unwrap(),
+            ));
+        }
+        assert_eq!(
+            vec![("tags".to_string(), IndexType::ArrayIndex)],
+            index_types
+        );
+
+        let query = db
+            .query(
+                r#"
+{
+ "WHAT": [[".doc._id"]],
+ "FROM": [{"as": "doc"}, {"as": "tag", "unnest": [".doc.tags"]}],
+ "WHERE": ["=", [".tag"], "red"],
+ "ORDER_BY": [[".doc._id"]]
+}
+"#,
+            )
+            .unwrap();
+        let ids: Vec<String> = query
+            .run_to_owned_rows()
+            .unwrap()
+            .iter()
+            .map(|row| row.get_checked::<&str>(0).unwrap().to_string())
+            .collect();
+        assert_eq!(vec!["a", "c"], ids);
+    }
+    tmp_dir.close().expect("Can not close tmp_dir");
+}
+
+#[test]
+fn test_bulk_import() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    println!("we create tempdir at {}", tmp_dir.path().display());
+    {
+        const N: i32 = 10_000;
+        let foos: Vec<Foo> = (0..N)
+            .map(|i| Foo {
+                i,
+                s: format!("Hello {}", i),
+            })
+            .collect();
+
+        let mut db =
+            Database::open_with_flags(&tmp_dir.path().join("a.cblite2"), DatabaseFlags::CREATE)
+                .unwrap();
+        let ids = db
+            .bulk_import(foos.iter().map(|foo| (None, foo)), 1_000)
+            .unwrap();
+        assert_eq!(foos.len(), ids.len());
+        assert_eq!(N as u64, db.document_count());
+        let foo: Foo = db.get_existing(&ids[17]).unwrap().decode_body().unwrap();
+        assert_eq!(foos[17], foo);
+
+        let ids = db.bulk_import([(Some("bulk_doc"), &foos[0])], 0).unwrap();
+        assert_eq!(vec!["bulk_doc".to_string()], ids);
+        assert_eq!(N as u64 + 1, db.document_count());
+    }
+    tmp_dir.close().expect("Can not close tmp_dir");
+}
+
 #[test]
 fn test_like_performance() {
     let _ = env_logger::try_init();
@@ -529,18 +981,19 @@ fn test_like_performance() {
             .unwrap();
         db.create_index("field2", "[[\".field2\"]]", IndexType::ValueIndex, None)
             .unwrap();
+        db.reindex().unwrap();
+        db.integrity_check().unwrap();
 
-        for i in 0..N {
-            let pat = format!("{}", i);
-            let query = db
-                .query(&format!(
-                    r#"{{
+        let query = db
+            .query(
+                r#"{
 "WHAT": [["count()"]],
- "WHERE": ["OR", ["LIKE", [".field1"], "%{pat}%"],
-                 ["LIKE", [".field2"], "%{pat}%"]]}}"#,
-                    pat = pat,
-                ))
-                .unwrap();
+ "WHERE": ["OR", ["LIKE", [".field1"], ["$pat"]],
+                 ["LIKE", [".field2"], ["$pat"]]]}"#,
+            )
+            .unwrap();
+        for i in 0..N {
+            query.set_parameter("pat", &format!("%{i}%")).unwrap();
             let mut iter = query.run().unwrap();
             let mut query_ret = Vec::with_capacity(10);
             while let Some(item) = iter.next().unwrap() {
@@ -620,6 +1073,83 @@ fn test_n1ql_query() {
 
             assert_eq!(expected, query_ret);
         }
+
+        {
+            let query = db
+                .n1ql_query("SELECT s, i AS num FROM a WHERE s LIKE '%555'")
+                .unwrap();
+            assert_eq!(2, query.column_count());
+            assert_eq!("s", query.column_title(0).unwrap());
+            assert_eq!("num", query.column_title(1).unwrap());
+            assert!(query.column_title(2).is_err());
+
+            let mut iter = query.run().unwrap();
+            let mut query_ret = Vec::with_capacity(10);
+            while let Some(item) = iter.next().unwrap() {
+                let row = item.as_row();
+                assert_eq!(2, row.column_count());
+                assert!(row["missing"].is_null());
+                let s = row["s"].as_str().unwrap();
+                assert_eq!(format!("Hello {}", row["num"].as_i64().unwrap()), s);
+                query_ret.push(s.to_string());
+            }
+            query_ret.sort();
+            assert_eq!(expected, query_ret);
+        }
+
+        {
+            let query = db
+                .n1ql_query(
+                    "SELECT s FROM a WHERE s LIKE '%555' ORDER BY s LIMIT $limit OFFSET $offset",
+                )
+                .unwrap();
+            query.set_parameter("limit", &3).unwrap();
+            query.set_parameter("offset", &2).unwrap();
+
+            let mut iter = query.run().unwrap();
+            let mut query_ret = Vec::with_capacity(3);
+            while let Some(item) = iter.next().unwrap() {
+                let val: &str = item.get_checked_serde(0).unwrap();
+                query_ret.push(val.to_string());
+            }
+            assert_eq!(&expected[2..5], query_ret);
+        }
+
+        #[cfg(feature = "async-query")]
+        {
+            use futures_util::StreamExt;
+
+            let query = db
+                .n1ql_query("SELECT s FROM a WHERE s LIKE '%555'")
+                .unwrap();
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            let mut query_ret: Vec<String> = rt.block_on(async {
+                assert!(query.run_async().await.is_err());
+                let mut stream = Box::pin(query.run().unwrap().into_sync_stream());
+                let mut ret = Vec::new();
+                while let Some(row) = stream.next().await {
+                    ret.push(row.unwrap().get_checked_serde::<String>(0).unwrap());
+                }
+                ret
+            });
+            query_ret.sort();
+            assert_eq!(expected, query_ret);
+
+            let rt = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .build()
+                .unwrap();
+            let mut query_ret: Vec<String> = rt
+                .block_on(async { query.run_async().await })
+                .unwrap()
+                .iter()
+                .map(|row| row.get_checked_serde::<String>(0).unwrap())
+                .collect();
+            query_ret.sort();
+            assert_eq!(expected, query_ret);
+        }
     }
     tmp_dir.close().expect("Can not close tmp_dir");
 }
@@ -664,6 +1194,91 @@ fn test_n1ql_query_with_parameter() {
         query_ret.sort();
 
         assert_eq!(expected, query_ret);
+
+        let rows = query.run_to_owned_rows().unwrap();
+        let query_ret = std::thread::spawn(move || {
+            rows.iter()
+                .map(|row| row.get_checked::<&str>(0).unwrap().to_string())
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(expected, query_ret);
+        let query = db
+            .n1ql_query("SELECT s FROM a WHERE s LIKE $pattern AND i < $max ORDER BY s")
+            .unwrap();
+        query
+            .set_parameters_fleece(serde_fleece::fleece!({
+                "pattern": "%555"
+            }))
+            .unwrap();
+        query.set_parameter("max", &3000).unwrap();
+        let query_ret: Vec<String> = query
+            .run_to_owned_rows()
+            .unwrap()
+            .iter()
+            .map(|row| row.get_checked::<&str>(0).unwrap().to_string())
+            .collect();
+        assert_eq!(vec!["Hello 1555", "Hello 2555", "Hello 555"], query_ret);
+
+        // borrowed values live as long as row, not as long as iterator's item
+        let rows = query.run_to_owned_rows().unwrap();
+        let borrowed: Vec<&str> = rows
+            .iter()
+            .map(|row| row.get_checked_serde::<&str>(0).unwrap())
+            .collect();
+        assert_eq!(vec!["Hello 1555", "Hello 2555", "Hello 555"], borrowed);
+        drop(rows);
+
+        query.clear_parameters().unwrap();
+        assert!(query.run_to_owned_rows().unwrap().is_empty());
+        query.set_parameter("max", &3000).unwrap();
+        assert!(query.run_to_owned_rows().unwrap().is_empty());
+    }
+    tmp_dir.close().expect("Can not close tmp_dir");
+}
+
+#[test]
+fn test_query_observer() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    println!("we create tempdir at {}", tmp_dir.path().display());
+    let db_path = tmp_dir.path().join("a.cblite2");
+    {
+        let db = Database::open_with_flags(&db_path, DatabaseFlags::CREATE).unwrap();
+        let mut query = db
+            .n1ql_query("SELECT s FROM _default WHERE type = 'Foo'")
+            .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        query
+            .observe(move || {
+                let _ = sender.send(());
+            })
+            .unwrap();
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        let mut iter = query.current_results().unwrap().unwrap();
+        assert!(iter.next().unwrap().is_none());
+        drop(iter);
+
+        let mut db2 = Database::open_with_flags(&db_path, DatabaseFlags::CREATE).unwrap();
+        let mut trans = db2.transaction().unwrap();
+        let foo = Foo {
+            i: 1,
+            s: "Hello 1".into(),
+        };
+        let enc = trans.shared_encoder_session().unwrap();
+        let mut doc = Document::new(&foo, enc).unwrap();
+        trans.save(&mut doc).unwrap();
+        trans.commit().unwrap();
+
+        receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        let mut iter = query.current_results().unwrap().unwrap();
+        let item = iter.next().unwrap().unwrap();
+        assert_eq!(
+            "Hello 1",
+            item.get_raw_checked(0).unwrap().as_str().unwrap()
+        );
+        assert!(iter.next().unwrap().is_none());
     }
     tmp_dir.close().expect("Can not close tmp_dir");
 }
@@ -757,6 +1372,60 @@ fn test_all_types_in_query() {
         assert_eq!(2, m.len());
         assert_eq!(17, m.get(FLSlice::from("a".as_bytes())).as_i32().unwrap());
         assert_eq!(18, m.get(FLSlice::from("b".as_bytes())).as_i32().unwrap());
+        assert_eq!(17, m.get_path("a").as_i32().unwrap());
+        assert!(m.get_path("a.b").is_null());
+        assert!(m.get_path("c.d").is_null());
+        let fleece_dict = m.as_fleece_dict().unwrap();
+        assert_eq!(Some(17), fleece_dict.get_as_i64("a"));
+        let m2: ValueRefDict = fleece_dict.into();
+        assert_eq!(18, m2.get_path("b").as_i32().unwrap());
+
+        let encoded = serde_fleece::to_fl_slice_result(&m_value).unwrap();
+        assert_eq!(
+            r#"{"a":17,"b":18}"#,
+            serde_fleece::fleece_to_json(encoded.as_bytes()).unwrap()
+        );
+        let m3 = unsafe {
+            ValueRef::from_fl_value(ffi::FLValue_FromData(
+                encoded.as_fl_slice(),
+                ffi::FLTrust::kFLUntrusted,
+            ))
+        };
+        let ValueRef::Dict(m3) = m3 else {
+            panic!("Expect dict type, got {m3:?}");
+        };
+        assert_eq!(17, m3.get_path("a").as_i32().unwrap());
+        let encoded = serde_fleece::to_fl_slice_result(&arr_value).unwrap();
+        assert_eq!(
+            format!(r#"["{}","{}","{}"]"#, boo.arr[0], boo.arr[1], boo.arr[2]),
+            serde_fleece::fleece_to_json(encoded.as_bytes()).unwrap()
+        );
+
+        let (b, int, long, ulong, float, double, s, arr, m): (
+            bool,
+            i32,
+            i64,
+            u64,
+            f32,
+            f64,
+            String,
+            [String; 3],
+            HashMap<String, i32>,
+        ) = item.get_row().unwrap();
+        assert_eq!(
+            boo,
+            Boo {
+                b,
+                int,
+                long,
+                ulong,
+                float,
+                double,
+                s,
+                arr,
+                m
+            }
+        );
 
         drop(iter);
         drop(query);
@@ -788,3 +1457,20 @@ fn test_all_types_in_query() {
     }
     fs::remove_dir_all(tmp_path).expect("Can not remove tmp_dir");
 }
+
+#[test]
+fn test_error_source() {
+    use std::error::Error as StdError;
+
+    let err: Error = serde_fleece::Error::Custom("boom".into()).into();
+    let source = err
+        .source()
+        .expect("serde error should be exposed as source");
+    assert_eq!(
+        source.to_string(),
+        serde_fleece::Error::Custom("boom".into()).to_string()
+    );
+
+    let err: Error = Error::LogicError("no source".into());
+    assert!(err.source().is_none());
+}
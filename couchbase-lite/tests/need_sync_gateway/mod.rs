@@ -31,20 +31,24 @@ fn test_double_replicator_restart() {
         let handle = runtime.handle().clone();
         let params = ReplicatorParameters::default()
             .with_auth(auth.clone())
-            .with_validation_func(
-                |coll_spec: C4CollectionSpec,
-                 doc_id: C4String,
-                 rev_id: C4String,
-                 rev_flags,
-                 _body| {
-                    let coll_name: &str =
-                        unsafe { str::from_utf8_unchecked(coll_spec.name.into()) };
-                    let doc_id: &str = unsafe { str::from_utf8_unchecked(doc_id.into()) };
-                    let rev_id: &str = unsafe { str::from_utf8_unchecked(rev_id.into()) };
-                    println!("Pull filter: {coll_name}, {doc_id}, {rev_id}, {rev_flags:?}");
-                    true
-                },
-            )
+            .with_extra_headers([(
+                "User-Agent".to_string(),
+                "couchbase-lite-rust-test".to_string(),
+            )])
+            .with_auto_retry(BackoffConfig {
+                max_attempts: Some(5),
+                ..Default::default()
+            })
+            .with_replicated_doc_filter(|doc: &ReplicatedDoc| {
+                println!(
+                    "Pull filter: {}, {}, {}, {:?}",
+                    doc.collection_name().unwrap(),
+                    doc.doc_id().unwrap(),
+                    doc.rev_id().unwrap(),
+                    doc.flags()
+                );
+                doc.is_deleted() || doc.body().is_some()
+            })
             .with_state_changed_callback(move |repl_state| {
                 println!("repl_state changed: {repl_state:?}");
                 if let ReplicatorState::Idle = repl_state {
@@ -56,13 +60,9 @@ fn test_double_replicator_restart() {
                 }
             })
             .with_documents_ended_callback(
-                move |pushing: bool, doc_iter: &mut dyn Iterator<Item = &C4DocumentEnded>| {
-                    let docs: Vec<String> = doc_iter
-                        .map(|x| {
-                            let doc_id: &str = x.docID.as_fl_slice().try_into().unwrap();
-                            doc_id.to_string()
-                        })
-                        .collect();
+                move |pushing: bool, doc_iter: &mut dyn Iterator<Item = DocumentEnded>| {
+                    let docs: Vec<String> =
+                        doc_iter.map(|x| x.doc_id().unwrap().to_string()).collect();
                     println!("pushing {pushing}, docs {docs:?}");
                 },
             );
@@ -87,12 +87,30 @@ fn test_double_replicator_restart() {
     };
     sync_rx.recv().unwrap();
     println!("got SYNC event that replicator was idle");
+    let headers = repl.response_headers().unwrap();
+    println!("response headers: {headers:?}");
+    assert!(!headers.unwrap().is_empty());
     for _ in 0..10 {
         repl = repl.restart(&db, url, &auth, false).unwrap();
     }
     println!("multi restart done");
     std::thread::sleep(std::time::Duration::from_secs(2));
+    assert_eq!(0, repl.document_error_count());
+    assert_eq!(0, repl.conflict_count());
+    repl.stop();
+
+    let unreachable_url = "ws://127.0.0.1:1/demo/";
+    let repl = Replicator::new_with_urls(
+        &db,
+        &[unreachable_url, url],
+        ReplicatorParameters::default().with_auth(auth.clone()),
+    )
+    .unwrap();
+    assert_eq!(unreachable_url, repl.current_url());
+    let mut repl = repl.failover(&db).unwrap();
+    assert_eq!(url, repl.current_url());
     repl.stop();
+
     stop_tx.send(()).unwrap();
     thread_join_handle.join().unwrap();
 
@@ -151,13 +169,9 @@ fn start_repl_and_save_documents(
                 }
             })
             .with_documents_ended_callback(
-                move |pushing: bool, doc_iter: &mut dyn Iterator<Item = &C4DocumentEnded>| {
-                    let docs: Vec<String> = doc_iter
-                        .map(|x| {
-                            let doc_id: &str = x.docID.as_fl_slice().try_into().unwrap();
-                            doc_id.to_string()
-                        })
-                        .collect();
+                move |pushing: bool, doc_iter: &mut dyn Iterator<Item = DocumentEnded>| {
+                    let docs: Vec<String> =
+                        doc_iter.map(|x| x.doc_id().unwrap().to_string()).collect();
                     println!("pushing {pushing}, docs {docs:?}");
                 },
             );
@@ -0,0 +1,148 @@
+//! Socket factory can be registered only once per process,
+//! so test of custom transport lives in separate test binary
+
+use couchbase_lite::*;
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Mutex},
+    thread,
+    time::Duration,
+};
+use tempfile::tempdir;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+enum Event {
+    Open(String, SocketHandle),
+    Write(usize),
+    RequestClose(i32),
+    Dispose,
+}
+
+/// In-memory transport: it never sends anything to LiteCore,
+/// just reports what LiteCore asks it to do
+struct MockSocketFactory {
+    events: Mutex<mpsc::Sender<Event>>,
+}
+
+enum Command {
+    CompletedWrite(usize),
+    Close,
+}
+
+struct MockSocket {
+    events: mpsc::Sender<Event>,
+    commands: mpsc::Sender<Command>,
+}
+
+impl Drop for MockSocket {
+    fn drop(&mut self) {
+        let _ = self.events.send(Event::Dispose);
+    }
+}
+
+impl SocketFactoryImpl for MockSocketFactory {
+    type Socket = MockSocket;
+
+    fn open(&self, socket: SocketHandle, url: &str, _options: Option<ValueRefDict>) -> MockSocket {
+        let events = self.events.lock().unwrap().clone();
+        let (commands, commands_rx) = mpsc::channel();
+        // report events to LiteCore in order from one thread
+        thread::spawn(move || {
+            for cmd in commands_rx {
+                match cmd {
+                    Command::CompletedWrite(n) => socket.completed_write(n),
+                    Command::Close => {
+                        socket.closed(None);
+                        break;
+                    }
+                }
+            }
+        });
+        events.send(Event::Open(url.to_string(), socket)).unwrap();
+        MockSocket { events, commands }
+    }
+
+    fn write(&self, sock: &mut MockSocket, _socket: SocketHandle, data: &[u8]) {
+        sock.events.send(Event::Write(data.len())).unwrap();
+        sock.commands
+            .send(Command::CompletedWrite(data.len()))
+            .unwrap();
+    }
+
+    fn completed_receive(&self, _sock: &mut MockSocket, _socket: SocketHandle, _byte_count: usize) {
+    }
+
+    fn request_close(
+        &self,
+        sock: &mut MockSocket,
+        _socket: SocketHandle,
+        status: i32,
+        _message: &str,
+    ) {
+        sock.events.send(Event::RequestClose(status)).unwrap();
+        sock.commands.send(Command::Close).unwrap();
+    }
+}
+
+#[test]
+fn test_custom_socket_factory() {
+    let _ = env_logger::try_init();
+    let tmp_dir = tempdir().expect("Can not create tmp directory");
+    println!("we create tempdir at {}", tmp_dir.path().display());
+    let db_path = tmp_dir.path().join("a.cblite2");
+    let (events_tx, events) = mpsc::channel();
+    Database::register_socket_factory(MockSocketFactory {
+        events: Mutex::new(events_tx),
+    });
+    {
+        let db = Database::open_with_flags(&db_path, DatabaseFlags::CREATE).unwrap();
+        let (state_tx, states) = mpsc::channel();
+        let params = ReplicatorParameters::default().with_state_changed_callback(
+            move |repl_state: ReplicatorState| {
+                println!("repl_state changed: {repl_state:?}");
+                let _ = state_tx.send(matches!(repl_state, ReplicatorState::Stopped(_)));
+            },
+        );
+        let mut repl = Replicator::new(&db, "ws://mock.invalid:4984/db", params).unwrap();
+        repl.start(false).unwrap();
+
+        let socket = match events.recv_timeout(TIMEOUT).unwrap() {
+            Event::Open(url, socket) => {
+                assert!(url.starts_with("ws://mock.invalid:4984/db"), "url {url}");
+                socket
+            }
+            ev => panic!("unexpected event {ev:?}"),
+        };
+        // events should not be reported before `open` returns
+        thread::sleep(Duration::from_millis(100));
+        let headers = serde_fleece::to_fl_slice_result(&HashMap::<String, String>::new()).unwrap();
+        socket.got_http_response(101, headers.as_bytes());
+        socket.opened();
+
+        match events.recv_timeout(TIMEOUT).unwrap() {
+            Event::Write(n) => assert!(n > 0),
+            ev => panic!("unexpected event {ev:?}"),
+        }
+
+        repl.stop();
+        loop {
+            match events.recv_timeout(TIMEOUT).unwrap() {
+                Event::Write(_) => {}
+                Event::RequestClose(_) => break,
+                ev => panic!("unexpected event {ev:?}"),
+            }
+        }
+        while !states.recv_timeout(TIMEOUT).unwrap() {}
+        drop(repl);
+        loop {
+            match events.recv_timeout(TIMEOUT).unwrap() {
+                Event::Write(_) => {}
+                Event::Dispose => break,
+                ev => panic!("unexpected event {ev:?}"),
+            }
+        }
+    }
+    tmp_dir.close().expect("Can not close tmp_dir");
+}
@@ -1,18 +1,10 @@
-use couchbase_lite::{
-    fallible_streaming_iterator::FallibleStreamingIterator,
-    ffi::{C4RevisionFlags, FLDict},
-    resolve_conflict,
-    serde_fleece::{from_fl_dict, Dict},
-    C4CollectionSpec, C4DocumentEnded, C4String, Database, DatabaseFlags, DocEnumeratorFlags,
-    Document, Replicator, ReplicatorAuthentication, ReplicatorParameters, ReplicatorState,
-};
+use couchbase_lite::{prelude::*, resolve_conflict, ConflictResolution};
 use log::{error, trace};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashSet,
     env,
     path::Path,
-    str,
     sync::{mpsc, Arc},
     time::Duration,
 };
@@ -142,9 +134,10 @@ fn fix_conflicts(db: &mut Database) -> Result<(), Box<dyn std::error::Error>> {
     {
         let mut it = db.enumerate_all_docs(DocEnumeratorFlags::empty())?;
         while let Some(item) = it.next()? {
-            let doc = item.get_doc()?;
-            println!("document with conflict {}", doc.id());
-            conflicts.push(doc.id().to_string());
+            if let Some(info) = item.get_doc_info()? {
+                println!("document with conflict {}", info.doc_id());
+                conflicts.push(info.doc_id().to_string());
+            }
         }
     }
     println!("There are {} conflicts in database", conflicts.len());
@@ -204,41 +197,41 @@ fn run_db_thread(
             }
         };
 
-        let repl_spawn2 = repl_spawn.clone();
         let mut my_db = if let Some(db) = db {
             let params = ReplicatorParameters::default()
                 .with_auth(auth)
                 .with_state_changed_callback(move |repl_state| {
-                    println!("replicator state changed: {repl_state:?}");
-                    if let ReplicatorState::Offline = repl_state {
-                        repl_spawn.spawn(|mdb| {
-                            if let Some(mdb) = mdb.as_mut() {
-                                println!("restarting replicator");
-                                std::thread::sleep(std::time::Duration::from_secs(5));
-                                mdb.repl.retry().expect("restart_replicator failed");
-                            } else {
-                                eprintln!("db is NOT open");
-                            }
-                        });
-                    }
+                    println!("replicator state changed: {repl_state}");
+                })
+                .with_auto_retry(BackoffConfig {
+                    initial_delay: Duration::from_secs(5),
+                    ..Default::default()
                 })
-                .with_documents_ended_callback(move |pushing: bool, doc_it: &mut dyn Iterator<Item = &C4DocumentEnded>| {
+                .with_documents_ended_callback(move |pushing: bool, doc_it: &mut dyn Iterator<Item = DocumentEnded>| {
                     for doc in doc_it {
-                        if !pushing && (doc.flags & C4RevisionFlags::kRevIsConflict) != C4RevisionFlags(0) {
-                            let doc_id: &str = doc.docID.as_fl_slice().try_into().unwrap();
-                            let doc_id = doc_id.to_string();
-                            let rev_id = <&[u8]>::from(doc.revID.as_fl_slice()).to_vec();
-                            repl_spawn2.spawn(move |mdb| {
-                                println!("there is conflict for ({}, {:?}) during replication, trying resolve",
-                                         doc_id, str::from_utf8(&rev_id));
+                        if let Some(err) = doc.error() {
+                            eprintln!("replication of {:?} failed (transient {}): {err}",
+                                      doc.doc_id(), doc.error_is_transient());
+                        }
+                        if !pushing && doc.is_conflict() {
+                            let doc_id = doc.doc_id().unwrap().to_string();
+                            let rev_id = doc.rev_id().unwrap_or_default().to_string();
+                            repl_spawn.spawn(move |mdb| {
+                                println!("there is conflict for ({}, {}) during replication, trying resolve",
+                                         doc_id, rev_id);
                                 if let Some(mdb) = mdb {
-                                    resolve_conflict(&mut mdb.db, &doc_id).expect("resolve conflict failed");
+                                    match resolve_conflict(&mut mdb.db, &doc_id).expect("resolve conflict failed") {
+                                        ConflictResolution::RemoteWon { rev_id } => {
+                                            println!("local edit of {doc_id} was overridden by remote, current revision {rev_id}");
+                                        }
+                                        resolution => println!("conflict for {doc_id} resolved: {resolution:?}"),
+                                    }
                                 }
                             });
                         }
                     }
                 })
-                .with_validation_func(input_doc_filter);
+                .with_replicated_doc_filter(input_doc_filter);
             let repl = Replicator::new(&db, &sync_url, params);
             match repl {
                 Ok(mut repl) => {
@@ -317,22 +310,29 @@ fn print_external_changes(mdb: &mut Option<MyDb>) -> Result<(), Box<dyn std::err
     let mdb = mdb
         .as_mut()
         .ok_or_else(|| format!("print_external_changes: db not OPEN"))?;
+    let changes = mdb.db.observed_changes();
+    let db = changes.database();
     let mut doc_ids = HashSet::<String>::new();
-    let db = &mut mdb.db;
-    for change in db.observed_changes() {
+    for change in changes {
         println!(
-            "observed change: doc id {} was changed, external {}, flags {}",
+            "observed change: doc id {} was changed, external {}, flags {}, generation {}, sequence {}, conflict {}",
             change.doc_id()?,
             change.external(),
             change.revision_flags().0,
+            change.generation(),
+            change.sequence(),
+            change.is_conflict(),
         );
-        if change.external() {
-            doc_ids.insert(change.doc_id()?.into());
+        if !change.external() || !doc_ids.insert(change.doc_id()?.into()) {
+            continue;
         }
-    }
-    for doc_id in &doc_ids {
-        let doc = match db.get_existing(doc_id.as_str()) {
-            Ok(x) => x,
+        let doc_id = change.doc_id()?;
+        let doc = match change.get_doc(db) {
+            Ok(Some(x)) => x,
+            Ok(None) => {
+                eprintln!("{doc_id} was purged");
+                continue;
+            }
             Err(err) => {
                 eprintln!("Can not get {doc_id}: {err}");
                 continue;
@@ -350,33 +350,26 @@ fn print_external_changes(mdb: &mut Option<MyDb>) -> Result<(), Box<dyn std::err
     Ok(())
 }
 
-fn input_doc_filter(
-    coll_spec: C4CollectionSpec,
-    doc_id: C4String,
-    rev_id: C4String,
-    flags: C4RevisionFlags,
-    body: FLDict,
-) -> bool {
-    if (flags & (C4RevisionFlags::kRevDeleted | C4RevisionFlags::kRevPurged)) != C4RevisionFlags(0)
-    {
+fn input_doc_filter(doc: &ReplicatedDoc) -> bool {
+    if doc.is_deleted() {
         return true;
     }
-    let (coll_name, doc_id, rev_id) = (
-        str::from_utf8(coll_spec.name.into()).unwrap(),
-        str::from_utf8(doc_id.into()).unwrap(),
-        str::from_utf8(rev_id.into()).unwrap(),
-    );
-
-    println!("Input filter: {coll_name} {doc_id} {rev_id} {flags:?}");
-    let body = match Dict::new(&body) {
-        Some(x) => x,
-        None => {
-            eprintln!("skip {doc_id}, body is null");
-            return false;
-        }
+    let (Ok(coll_name), Ok(doc_id), Ok(rev_id)) =
+        (doc.collection_name(), doc.doc_id(), doc.rev_id())
+    else {
+        return false;
     };
-    match body.get_as_str("type") {
-        Some("Message") => from_fl_dict::<Message, _>(body).is_ok(),
+
+    println!(
+        "Input filter: {coll_name} {doc_id} {rev_id} {:?}",
+        doc.flags()
+    );
+    if doc.body().is_none() {
+        eprintln!("skip {doc_id}, body is null");
+        return false;
+    }
+    match doc.get_as_str("type") {
+        Some("Message") => doc.decode::<Message>().is_ok(),
         Some(_) | None => false,
     }
 }
@@ -0,0 +1,68 @@
+use crate::{
+    ffi::{
+        FLError, FLMutableArray_AppendBool, FLMutableArray_AppendInt, FLMutableArray_AppendString,
+        FLMutableArray_AppendValue, FLMutableArray_New, FLMutableArray_Release, FLSliceResult,
+        _FLArray, _FLValue,
+    },
+    ser::encode_fl_value,
+    Error, MutableDict, NonNullConst,
+};
+use std::ptr::NonNull;
+
+#[repr(transparent)]
+pub struct MutableArray(NonNull<_FLArray>);
+
+impl MutableArray {
+    #[inline]
+    pub fn new() -> Result<Self, Error> {
+        let array = unsafe { FLMutableArray_New() };
+        NonNull::new(array)
+            .ok_or(Error::Fleece(FLError::kFLMemoryError))
+            .map(MutableArray)
+    }
+    #[inline]
+    pub fn append_i64(&mut self, value: i64) {
+        unsafe { FLMutableArray_AppendInt(self.0.as_ptr(), value) };
+    }
+    #[inline]
+    pub fn append_string(&mut self, value: &str) {
+        unsafe { FLMutableArray_AppendString(self.0.as_ptr(), value.into()) };
+    }
+    #[inline]
+    pub fn append_bool(&mut self, value: bool) {
+        unsafe { FLMutableArray_AppendBool(self.0.as_ptr(), value) };
+    }
+    /// Append any fleece value, value will be retained by array
+    #[inline]
+    pub fn append_value(&mut self, value: NonNullConst<_FLValue>) {
+        unsafe { FLMutableArray_AppendValue(self.0.as_ptr(), value.as_ptr()) };
+    }
+    /// Append nested dictionary, it is shared (not copied),
+    /// so later changes of `dict` are visible via array
+    #[inline]
+    pub fn append_dict(&mut self, dict: &MutableDict) {
+        self.append_value(dict.as_dict().cast());
+    }
+    /// Append nested array, it is shared (not copied),
+    /// so later changes of `array` are visible via this array
+    #[inline]
+    pub fn append_array(&mut self, array: &MutableArray) {
+        self.append_value(array.as_array().cast());
+    }
+    #[inline]
+    pub fn as_array(&self) -> NonNullConst<_FLArray> {
+        self.0.into()
+    }
+    /// Encode array, so it can be saved
+    #[inline]
+    pub fn to_fl_slice_result(&self) -> Result<FLSliceResult, Error> {
+        encode_fl_value(self.as_array().cast())
+    }
+}
+
+impl Drop for MutableArray {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { FLMutableArray_Release(self.0.as_ptr()) };
+    }
+}
@@ -1,21 +1,22 @@
 mod dict;
 mod seq;
 
-use std::{borrow::Borrow, marker::PhantomData, ptr::NonNull};
+use std::{borrow::Borrow, fmt, marker::PhantomData, ptr, ptr::NonNull, str::FromStr};
 
 use self::dict::DictAccess;
 use crate::{
     de::{dict::EnumAccess, seq::ArrayAccess},
     ffi::{
-        FLArray_Count, FLDict_Count, FLTrust, FLValueType, FLValue_AsArray, FLValue_AsBool,
-        FLValue_AsDict, FLValue_AsDouble, FLValue_AsFloat, FLValue_AsInt, FLValue_AsString,
-        FLValue_AsUnsigned, FLValue_FromData, FLValue_GetType, FLValue_IsDouble, FLValue_IsInteger,
-        FLValue_IsUnsigned, _FLDict, _FLValue,
+        FLArray_Count, FLDict_Count, FLSharedKeys, FLTrust, FLValueType, FLValue_AsArray,
+        FLValue_AsBool, FLValue_AsData, FLValue_AsDict, FLValue_AsDouble, FLValue_AsFloat,
+        FLValue_AsInt, FLValue_AsString, FLValue_AsUnsigned, FLValue_FromData, FLValue_GetType,
+        FLValue_IsDouble, FLValue_IsInteger, FLValue_IsUnsigned, FLValue_ToJSON, _FLDict,
+        _FLValue,
     },
-    Error,
+    Error, SharedKeys,
 };
 use itoa::Integer;
-use serde::de::{self, IntoDeserializer};
+use serde::de::{self, value::SeqDeserializer, IntoDeserializer};
 
 #[repr(transparent)]
 #[derive(Clone, Copy)]
@@ -48,6 +49,19 @@ impl<T> NonNullConst<T> {
         // SAFETY: `self` is a `NonNull` pointer which is necessarily non-null
         unsafe { NonNullConst::new_unchecked(self.as_ptr() as *mut U) }
     }
+    /// Convert to `NonNull`, it is up to caller to not modify pointed data
+    #[must_use]
+    #[inline]
+    pub const fn as_non_null(self) -> NonNull<T> {
+        // SAFETY: `self` is necessarily non-null
+        unsafe { NonNull::new_unchecked(self.0 as *mut T) }
+    }
+}
+
+impl<T> fmt::Debug for NonNullConst<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.0, f)
+    }
 }
 
 impl<T> From<NonNull<T>> for NonNullConst<T> {
@@ -59,21 +73,27 @@ impl<T> From<NonNull<T>> for NonNullConst<T> {
 
 pub(crate) struct Deserializer<'de> {
     pub value: NonNullConst<_FLValue>,
+    /// Used to decode integer keys of dictionaries, null if not set
+    pub shared_keys: FLSharedKeys,
     marker: PhantomData<&'de [u8]>,
 }
 
 impl<'de> Deserializer<'de> {
     fn new(value: NonNullConst<_FLValue>) -> Self {
+        Self::with_shared_keys(value, ptr::null_mut())
+    }
+    pub(crate) fn with_shared_keys(
+        value: NonNullConst<_FLValue>,
+        shared_keys: FLSharedKeys,
+    ) -> Self {
         Self {
             value,
+            shared_keys,
             marker: PhantomData,
         }
     }
     fn from_slice(input: &'de [u8]) -> Result<Self, Error> {
-        let fl_val = unsafe { FLValue_FromData(input.into(), FLTrust::kFLUntrusted) };
-        let fl_val = NonNullConst::new(fl_val)
-            .ok_or_else(|| Error::InvalidFormat("untrusted data validation failed".into()))?;
-        Ok(Self::new(fl_val))
+        validated_root(input).map(Self::new)
     }
 
     fn parse_signed<T: Integer + TryFrom<i64>>(&self) -> Result<T, Error> {
@@ -108,6 +128,29 @@ impl<'de> Deserializer<'de> {
         }
     }
 
+    /// 128-bit integers are stored as decimal strings,
+    /// but accept also ordinary fleece integers
+    fn parse_wide<T: FromStr + TryFrom<i64> + TryFrom<u64>>(&self) -> Result<T, Error> {
+        let ty = unsafe { FLValue_GetType(self.value.as_ptr()) };
+        match ty {
+            FLValueType::kFLString => self
+                .parse_str()?
+                .parse()
+                .map_err(|_err| Error::InvalidFormat("parsing of 128-bit integer failed".into())),
+            FLValueType::kFLNumber if unsafe { FLValue_IsUnsigned(self.value.as_ptr()) } => {
+                T::try_from(unsafe { FLValue_AsUnsigned(self.value.as_ptr()) })
+                    .map_err(|_err| Error::InvalidFormat("Can not convert u64 to integer".into()))
+            }
+            FLValueType::kFLNumber if unsafe { FLValue_IsInteger(self.value.as_ptr()) } => {
+                T::try_from(unsafe { FLValue_AsInt(self.value.as_ptr()) })
+                    .map_err(|_err| Error::InvalidFormat("Can not convert i64 to integer".into()))
+            }
+            _ => Err(Error::InvalidFormat(
+                format!("Wrong data type: expect kFLString or integer, got {ty:?}").into(),
+            )),
+        }
+    }
+
     fn parse_str(&self) -> Result<&'de str, Error> {
         let ty = unsafe { FLValue_GetType(self.value.as_ptr()) };
         if ty == FLValueType::kFLString {
@@ -119,6 +162,17 @@ impl<'de> Deserializer<'de> {
             ))
         }
     }
+
+    fn parse_data(&self) -> Result<&'de [u8], Error> {
+        let ty = unsafe { FLValue_GetType(self.value.as_ptr()) };
+        if ty == FLValueType::kFLData {
+            Ok(unsafe { FLValue_AsData(self.value.as_ptr()) }.into())
+        } else {
+            Err(Error::InvalidFormat(
+                format!("Wrong data type: expect kFLData, got {ty:?}").into(),
+            ))
+        }
+    }
 }
 
 pub fn from_slice<'a, T>(s: &'a [u8]) -> Result<T, Error>
@@ -129,6 +183,47 @@ where
     T::deserialize(&mut deserializer)
 }
 
+/// Check that `data` is valid fleece, for example before passing
+/// data received from peer to API that trusts its input
+#[inline]
+pub fn validate(data: &[u8]) -> Result<(), Error> {
+    validated_root(data).map(|_| ())
+}
+
+fn validated_root(data: &[u8]) -> Result<NonNullConst<_FLValue>, Error> {
+    NonNullConst::new(unsafe { FLValue_FromData(data.into(), FLTrust::kFLUntrusted) })
+        .ok_or_else(|| Error::InvalidFormat("untrusted data validation failed".into()))
+}
+
+/// Convert fleece encoded data into JSON text
+pub fn fleece_to_json(data: &[u8]) -> Result<String, Error> {
+    let value = validated_root(data)?;
+    let json = unsafe { FLValue_ToJSON(value.as_ptr()) };
+    Ok(std::str::from_utf8(json.as_bytes())?.to_string())
+}
+
+/// Convert fleece dictionary into JSON text
+pub fn fleece_dict_to_json<Dict>(dict: Dict) -> Result<String, Error>
+where
+    Dict: Borrow<NonNullConst<_FLDict>>,
+{
+    let value: NonNullConst<_FLValue> = dict.borrow().cast();
+    let json = unsafe { FLValue_ToJSON(value.as_ptr()) };
+    Ok(std::str::from_utf8(json.as_bytes())?.to_string())
+}
+
+/// Deserialize fleece data that was encoded with shared keys,
+/// for example document's body copied outside of database.
+/// Without shared keys such dictionary's keys can not be decoded.
+pub fn from_slice_with_shared_keys<'a, T>(s: &'a [u8], shared_keys: &SharedKeys) -> Result<T, Error>
+where
+    T: de::Deserialize<'a>,
+{
+    let root = validated_root(s)?;
+    let mut deserializer = Deserializer::with_shared_keys(root, shared_keys.as_ptr());
+    T::deserialize(&mut deserializer)
+}
+
 pub fn from_fl_dict<'a, T, Dict>(dict: Dict) -> Result<T, Error>
 where
     T: de::Deserialize<'a>,
@@ -139,6 +234,21 @@ where
     T::deserialize(&mut deserializer)
 }
 
+/// Like `from_fl_dict`, but integer keys of dictionaries are decoded
+/// with `shared_keys`, for example body of document passed to replicator's filter
+pub fn from_fl_dict_with_shared_keys<'a, T, Dict>(
+    dict: Dict,
+    shared_keys: &SharedKeys,
+) -> Result<T, Error>
+where
+    T: de::Deserialize<'a>,
+    Dict: Borrow<NonNullConst<_FLDict>>,
+{
+    let value: NonNullConst<_FLValue> = dict.borrow().cast();
+    let mut deserializer = Deserializer::<'a>::with_shared_keys(value, shared_keys.as_ptr());
+    T::deserialize(&mut deserializer)
+}
+
 pub fn from_fl_value<'a, T: de::Deserialize<'a>>(
     value: NonNullConst<_FLValue>,
 ) -> Result<T, Error> {
@@ -172,9 +282,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                 }
             }
             FLValueType::kFLString => self.deserialize_str(visitor),
-            FLValueType::kFLData => Err(Error::Unsupported(
-                "deserialize self described: `data` not supported",
-            )),
+            FLValueType::kFLData => self.deserialize_bytes(visitor),
             FLValueType::kFLArray => self.deserialize_seq(visitor),
             FLValueType::kFLDict => self.deserialize_map(visitor),
         }
@@ -222,6 +330,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_i64(self.parse_signed()?)
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_i128(self.parse_wide()?)
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
@@ -250,6 +365,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_u64(self.parse_unsigned()?)
     }
 
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_u128(self.parse_wide()?)
+    }
+
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
@@ -309,20 +431,18 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_str(visitor)
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::Unsupported("deserialization of bytes not supported"))
+        visitor.visit_borrowed_bytes(self.parse_data()?)
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        Err(Error::Unsupported(
-            "deserialization of byte buf not supported",
-        ))
+        self.deserialize_bytes(visitor)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
@@ -384,7 +504,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             let n: usize = n.try_into().map_err(|err| {
                 Error::InvalidFormat(format!("Can not convert {} to usize: {}", n, err).into())
             })?;
-            visitor.visit_seq(ArrayAccess::new(arr, n))
+            visitor.visit_seq(ArrayAccess::new(arr, n, self.shared_keys))
+        } else if ty == FLValueType::kFLData {
+            // for example `Vec<u8>` asks for sequence
+            let mut seq = SeqDeserializer::<_, Error>::new(self.parse_data()?.iter().copied());
+            let ret = visitor.visit_seq(&mut seq)?;
+            seq.end()?;
+            Ok(ret)
         } else {
             Err(Error::InvalidFormat(
                 format!("Wrong data type: expect kFLArray, got {ty:?}").into(),
@@ -429,7 +555,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         let n: usize = n.try_into().map_err(|err| {
             Error::InvalidFormat(format!("Can not convert {} to usize: {}", n, err).into())
         })?;
-        visitor.visit_map(DictAccess::new(dict, n))
+        visitor.visit_map(DictAccess::new(dict, n, self.shared_keys))
     }
 
     fn deserialize_struct<V>(
@@ -442,10 +568,15 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: de::Visitor<'de>,
     {
         let fv_type = unsafe { FLValue_GetType(self.value.as_ptr()) };
+        // array is treated as list of fields in order of declaration,
+        // like columns of query's row
+        if fv_type == FLValueType::kFLArray {
+            return self.deserialize_seq(visitor);
+        }
         if fv_type != FLValueType::kFLDict {
             return Err(Error::InvalidFormat(
                 format!(
-                    "For struct {} fleece data should be dict type, but got: {:?}",
+                    "For struct {} fleece data should be dict or array type, but got: {:?}",
                     name, fv_type
                 )
                 .into(),
@@ -461,7 +592,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             Error::InvalidFormat(format!("Can not convert {} to usize: {}", dict_size, err).into())
         })?;
 
-        visitor.visit_map(DictAccess::new(dict, dict_size))
+        visitor.visit_map(DictAccess::new(dict, dict_size, self.shared_keys))
     }
 
     fn deserialize_enum<V>(
@@ -484,7 +615,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                 let dict = NonNullConst::new(dict).ok_or_else(|| {
                     Error::InvalidFormat(format!("enum {} has not dict type (null)", name).into())
                 })?;
-                visitor.visit_enum(EnumAccess::new(dict))
+                visitor.visit_enum(EnumAccess::new(dict, self.shared_keys))
             }
             _ => Err(Error::InvalidFormat(
                 format!("Invalid type {:?} for enum {}", ftype, name).into(),
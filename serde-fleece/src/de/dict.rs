@@ -3,22 +3,44 @@ use crate::{
     de::Deserializer,
     ffi::{
         FLDictIterator, FLDictIterator_Begin, FLDictIterator_End, FLDictIterator_GetCount,
-        FLDictIterator_GetKeyString, FLDictIterator_GetValue, FLDictIterator_Next, _FLDict,
+        FLDictIterator_GetKey, FLDictIterator_GetKeyString, FLDictIterator_GetValue,
+        FLDictIterator_Next, FLSharedKeys, FLSharedKeys_Decode, FLString, FLValueType,
+        FLValue_AsInt, FLValue_GetType, _FLDict,
     },
     Error,
 };
 use serde::de::{self, IntoDeserializer};
-use std::{marker::PhantomData, mem::MaybeUninit, str::FromStr};
+use std::{marker::PhantomData, mem::MaybeUninit, os::raw::c_int, str::FromStr};
+
+/// Key of current dictionary's item, integer key is decoded
+/// with `shared_keys` if fleece can not find shared keys by itself
+unsafe fn key_string(it: &FLDictIterator, shared_keys: FLSharedKeys) -> FLString {
+    let key = FLDictIterator_GetKeyString(it);
+    if !key.buf.is_null() || shared_keys.is_null() {
+        return key;
+    }
+    let key = FLDictIterator_GetKey(it);
+    if !key.is_null() && FLValue_GetType(key) == FLValueType::kFLNumber {
+        match c_int::try_from(FLValue_AsInt(key)) {
+            Ok(key) => FLSharedKeys_Decode(shared_keys, key),
+            Err(_) => FLString::default(),
+        }
+    } else {
+        FLString::default()
+    }
+}
 
 pub(crate) struct EnumAccess<'a> {
     dict: NonNullConst<_FLDict>,
+    shared_keys: FLSharedKeys,
     marker: PhantomData<&'a [u8]>,
 }
 
 impl<'a> EnumAccess<'a> {
-    pub fn new(dict: NonNullConst<_FLDict>) -> Self {
+    pub fn new(dict: NonNullConst<_FLDict>, shared_keys: FLSharedKeys) -> Self {
         Self {
             dict,
+            shared_keys,
             marker: PhantomData,
         }
     }
@@ -43,14 +65,14 @@ impl<'de, 'a> de::EnumAccess<'de> for EnumAccess<'a> {
                     format!("enum should be dict with len 1, got {}", n).into(),
                 ));
             }
-            let key: &str = FLDictIterator_GetKeyString(&it).try_into()?;
+            let key: &str = key_string(&it, self.shared_keys).try_into()?;
             let key = <&str as de::IntoDeserializer<'_, Error>>::into_deserializer(key);
             let key = seed.deserialize(key)?;
             let value = FLDictIterator_GetValue(&it);
             let value = NonNullConst::new(value).ok_or_else(|| {
                 Error::InvalidFormat("not expecting null value in enum dict".into())
             })?;
-            Ok((key, Deserializer::new(value)))
+            Ok((key, Deserializer::with_shared_keys(value, self.shared_keys)))
         }
     }
 }
@@ -92,6 +114,7 @@ impl<'de> de::VariantAccess<'de> for Deserializer<'de> {
 pub(crate) struct DictAccess<'a> {
     n: usize,
     it: FLDictIterator,
+    shared_keys: FLSharedKeys,
     marker: PhantomData<&'a [u8]>,
 }
 
@@ -103,7 +126,7 @@ impl<'a> Drop for DictAccess<'a> {
 }
 
 impl<'a> DictAccess<'a> {
-    pub fn new(dict: NonNullConst<_FLDict>, n: usize) -> Self {
+    pub fn new(dict: NonNullConst<_FLDict>, n: usize, shared_keys: FLSharedKeys) -> Self {
         let mut it = MaybeUninit::<FLDictIterator>::uninit();
         let it = unsafe {
             FLDictIterator_Begin(dict.as_ptr(), it.as_mut_ptr());
@@ -112,6 +135,7 @@ impl<'a> DictAccess<'a> {
         Self {
             n,
             it,
+            shared_keys,
             marker: PhantomData,
         }
     }
@@ -125,7 +149,7 @@ impl<'a, 'de> de::MapAccess<'de> for DictAccess<'a> {
         K: de::DeserializeSeed<'de>,
     {
         if unsafe { FLDictIterator_GetCount(&self.it) } > 0 {
-            let key: &str = unsafe { FLDictIterator_GetKeyString(&self.it) }.try_into()?;
+            let key: &str = unsafe { key_string(&self.it, self.shared_keys) }.try_into()?;
             let key = de::DeserializeSeed::deserialize(seed, DictKeySerializer(key))?;
             Ok(Some(key))
         } else {
@@ -142,7 +166,14 @@ impl<'a, 'de> de::MapAccess<'de> for DictAccess<'a> {
             let value = FLDictIterator_GetValue(&self.it);
             let value = NonNullConst::new(value)
                 .ok_or_else(|| Error::InvalidFormat("not expecting null value in dict".into()))?;
-            let value = de::DeserializeSeed::deserialize(seed, &mut Deserializer::new(value))?;
+            let mut deserializer = Deserializer::with_shared_keys(value, self.shared_keys);
+            let value =
+                de::DeserializeSeed::deserialize(seed, &mut deserializer).map_err(|err| {
+                    let key: &str = key_string(&self.it, self.shared_keys)
+                        .try_into()
+                        .unwrap_or("<invalid utf-8>");
+                    err.in_dict(key)
+                })?;
             FLDictIterator_Next(&mut self.it);
             Ok(value)
         }
@@ -207,6 +238,13 @@ impl<'de, 'a> de::Deserializer<'de> for DictKeySerializer<'a> {
         visit_from_str!(self, visitor, i64, visit_i64)
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visit_from_str!(self, visitor, i128, visit_i128)
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
@@ -235,6 +273,13 @@ impl<'de, 'a> de::Deserializer<'de> for DictKeySerializer<'a> {
         visit_from_str!(self, visitor, u64, visit_u64)
     }
 
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visit_from_str!(self, visitor, u128, visit_u128)
+    }
+
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
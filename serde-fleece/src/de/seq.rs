@@ -2,7 +2,7 @@ use std::marker::PhantomData;
 
 use crate::{
     de::Deserializer,
-    ffi::{FLArray_Get, _FLArray},
+    ffi::{FLArray_Get, FLSharedKeys, _FLArray},
     Error,
 };
 use serde::de;
@@ -13,15 +13,17 @@ pub(crate) struct ArrayAccess<'a> {
     arr: NonNullConst<_FLArray>,
     n: usize,
     i: usize,
+    shared_keys: FLSharedKeys,
     marker: PhantomData<&'a [u8]>,
 }
 
 impl<'a> ArrayAccess<'a> {
-    pub fn new(arr: NonNullConst<_FLArray>, n: usize) -> Self {
+    pub fn new(arr: NonNullConst<_FLArray>, n: usize, shared_keys: FLSharedKeys) -> Self {
         Self {
             arr,
             n,
             i: 0,
+            shared_keys,
             marker: PhantomData,
         }
     }
@@ -38,7 +40,10 @@ impl<'de, 'a> de::SeqAccess<'de> for ArrayAccess<'a> {
             let value = unsafe { FLArray_Get(self.arr.as_ptr(), self.i as u32) };
             let value = NonNullConst::new(value)
                 .ok_or_else(|| Error::InvalidFormat("not expecting null value in array".into()))?;
-            let value = seed.deserialize(&mut Deserializer::new(value)).map(Some)?;
+            let value = seed
+                .deserialize(&mut Deserializer::with_shared_keys(value, self.shared_keys))
+                .map(Some)
+                .map_err(|err| err.in_array(self.i))?;
             self.i += 1;
             Ok(value)
         } else {
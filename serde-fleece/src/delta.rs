@@ -0,0 +1,34 @@
+use crate::{
+    ffi::{
+        FLApplyJSONDelta, FLCreateJSONDelta, FLError, FLSliceResult, FLTrust, FLValue_FromData,
+        _FLValue,
+    },
+    Error, NonNullConst,
+};
+
+/// Create JSON delta between two fleece encoded values,
+/// empty result means that values are equal
+pub fn create_delta(old: &[u8], new: &[u8]) -> Result<FLSliceResult, Error> {
+    let old = value_from_slice(old)?;
+    let new = value_from_slice(new)?;
+    Ok(unsafe { FLCreateJSONDelta(old.as_ptr(), new.as_ptr()) })
+}
+
+/// Apply JSON delta created by `create_delta` to fleece encoded value,
+/// returns fleece encoded result
+pub fn apply_delta(old: &[u8], delta: &[u8]) -> Result<FLSliceResult, Error> {
+    let old = value_from_slice(old)?;
+    let mut err = FLError::kFLNoError;
+    let ret = unsafe { FLApplyJSONDelta(old.as_ptr(), delta.into(), &mut err) };
+    if !ret.is_empty() {
+        Ok(ret)
+    } else {
+        Err(err.into())
+    }
+}
+
+fn value_from_slice(data: &[u8]) -> Result<NonNullConst<_FLValue>, Error> {
+    let fl_val = unsafe { FLValue_FromData(data.into(), FLTrust::kFLUntrusted) };
+    NonNullConst::new(fl_val)
+        .ok_or_else(|| Error::InvalidFormat("untrusted data validation failed".into()))
+}
@@ -1,13 +1,16 @@
-use couchbase_lite_core_sys::{FLValue_AsString, FLValue_GetType};
+use couchbase_lite_core_sys::{FLValue_AsString, FLValue_GetType, FLValue_ToJSON};
 
 use crate::{
     ffi::{
-        FLDict, FLDict_Get, FLError, FLMutableDict_New, FLMutableDict_Release,
-        FLMutableDict_SetInt, FLMutableDict_SetString, FLSlice, FLValueType, FLValue_AsData,
-        _FLDict, _FLValue,
+        FLCopyFlags_kFLDeepCopyImmutables, FLDict, FLDict_Get, FLDict_MutableCopy, FLError,
+        FLMutableDict_New, FLMutableDict_Release, FLMutableDict_SetInt, FLMutableDict_SetString,
+        FLMutableDict_SetValue, FLSlice, FLTrust, FLValueType, FLValue_AsArray, FLValue_AsBool,
+        FLValue_AsData, FLValue_AsDict, FLValue_AsDouble, FLValue_AsInt, FLValue_AsUnsigned,
+        FLValue_FromData, FLValue_IsInteger, FLValue_IsUnsigned, _FLArray, _FLDict, _FLValue,
     },
-    Error, NonNullConst,
+    to_fl_slice_result, Error, MutableArray, NonNullConst,
 };
+use serde::Serialize;
 use std::{borrow::Borrow, marker::PhantomData, ptr::NonNull};
 
 #[repr(transparent)]
@@ -21,6 +24,25 @@ impl MutableDict {
             .ok_or(Error::Fleece(FLError::kFLMemoryError))
             .map(MutableDict)
     }
+    /// Create dictionary from value that serialized as map or struct,
+    /// so it is possible to modify some fields before saving
+    pub fn from_serialize<T: Serialize>(value: &T) -> Result<Self, Error> {
+        let data = to_fl_slice_result(value)?;
+        let fl_val = unsafe { FLValue_FromData(data.as_fl_slice(), FLTrust::kFLTrusted) };
+        let fl_val = NonNullConst::new(fl_val)
+            .ok_or_else(|| Error::InvalidFormat("encoded data validation failed".into()))?;
+        let dict = unsafe { FLValue_AsDict(fl_val.as_ptr()) };
+        if dict.is_null() {
+            return Err(Error::InvalidFormat(
+                "value is not serialized as dictionary".into(),
+            ));
+        }
+        // deep copy, because of `dict` points to `data`
+        let copy = unsafe { FLDict_MutableCopy(dict, FLCopyFlags_kFLDeepCopyImmutables) };
+        NonNull::new(copy)
+            .ok_or(Error::Fleece(FLError::kFLMemoryError))
+            .map(MutableDict)
+    }
     #[inline]
     pub fn set_string(&mut self, key: &str, value: &str) {
         unsafe { FLMutableDict_SetString(self.0.as_ptr(), key.into(), value.into()) };
@@ -29,6 +51,36 @@ impl MutableDict {
     pub fn set_i64(&mut self, key: &str, value: i64) {
         unsafe { FLMutableDict_SetInt(self.0.as_ptr(), key.into(), value) };
     }
+    /// Set nested dictionary, it is shared (not copied),
+    /// so later changes of `dict` are visible via this dictionary
+    #[inline]
+    pub fn set_dict(&mut self, key: &str, dict: &MutableDict) {
+        let value: NonNull<_FLValue> = dict.0.cast();
+        unsafe { FLMutableDict_SetValue(self.0.as_ptr(), key.into(), value.as_ptr()) };
+    }
+    /// Set nested array, it is shared (not copied),
+    /// so later changes of `array` are visible via this dictionary
+    #[inline]
+    pub fn set_array(&mut self, key: &str, array: &MutableArray) {
+        let value: NonNullConst<_FLValue> = array.as_array().cast();
+        unsafe { FLMutableDict_SetValue(self.0.as_ptr(), key.into(), value.as_ptr()) };
+    }
+    #[inline]
+    pub fn get_as_str(&self, key: &str) -> Option<&str> {
+        get_as_str(self.as_dict(), key)
+    }
+    #[inline]
+    pub fn get_as_i64(&self, key: &str) -> Option<i64> {
+        get_as_i64(self.as_dict(), key)
+    }
+    #[inline]
+    pub fn get_as_bool(&self, key: &str) -> Option<bool> {
+        get_as_bool(self.as_dict(), key)
+    }
+    #[inline]
+    pub fn get_as_f64(&self, key: &str) -> Option<f64> {
+        get_as_f64(self.as_dict(), key)
+    }
     #[inline]
     pub fn as_dict(&self) -> NonNullConst<_FLDict> {
         self.0.into()
@@ -61,15 +113,55 @@ impl<'a> Dict<'a> {
             marker: PhantomData,
         })
     }
+    #[inline]
     pub fn get_as_str(&self, prop_name: &str) -> Option<&str> {
-        let val = unsafe { FLDict_Get(self.inner.as_ptr(), prop_name.into()) };
-        let val = NonNullConst::new(val)?;
-        if unsafe { FLValue_GetType(val.as_ptr()) } == FLValueType::kFLString {
-            let raw_s = unsafe { FLValue_AsString(val.as_ptr()) };
-            raw_s.try_into().ok()
-        } else {
-            None
-        }
+        get_as_str(self.inner, prop_name)
+    }
+    #[inline]
+    pub fn get_as_i64(&self, prop_name: &str) -> Option<i64> {
+        get_as_i64(self.inner, prop_name)
+    }
+    #[inline]
+    pub fn get_as_u64(&self, prop_name: &str) -> Option<u64> {
+        get_as_u64(self.inner, prop_name)
+    }
+    #[inline]
+    pub fn get_as_f64(&self, prop_name: &str) -> Option<f64> {
+        get_as_f64(self.inner, prop_name)
+    }
+    #[inline]
+    pub fn get_as_bool(&self, prop_name: &str) -> Option<bool> {
+        get_as_bool(self.inner, prop_name)
+    }
+    /// Get nested dictionary
+    pub fn get_dict(&self, prop_name: &str) -> Option<Dict<'a>> {
+        let val = get_typed(self.inner, prop_name, FLValueType::kFLDict)?;
+        let inner = NonNullConst::new(unsafe { FLValue_AsDict(val.as_ptr()) })?;
+        Some(Dict {
+            inner,
+            marker: PhantomData,
+        })
+    }
+    /// Walk nested dictionaries by path like "a.b.c",
+    /// returns `None` if any part of path is missing or is not dictionary
+    pub fn get_path(&self, path: &str) -> Option<NonNullConst<_FLValue>> {
+        let (parent, key) = match path.rsplit_once('.') {
+            Some((parent, key)) => (self.get_path(parent)?, key),
+            None => (self.inner.cast(), path),
+        };
+        let dict = NonNullConst::new(unsafe { FLValue_AsDict(parent.as_ptr()) })?;
+        NonNullConst::new(unsafe { FLDict_Get(dict.as_ptr(), key.into()) })
+    }
+    /// Get nested array
+    pub fn get_array(&self, prop_name: &str) -> Option<NonNullConst<_FLArray>> {
+        let val = get_typed(self.inner, prop_name, FLValueType::kFLArray)?;
+        NonNullConst::new(unsafe { FLValue_AsArray(val.as_ptr()) })
+    }
+    /// Convert dictionary to JSON, for example for logging
+    pub fn to_json(&self) -> String {
+        let value: NonNullConst<_FLValue> = self.inner.cast();
+        let json = unsafe { FLValue_ToJSON(value.as_ptr()) };
+        json.as_utf8_lossy().into_owned()
     }
 }
 
@@ -79,3 +171,53 @@ impl<'a> Borrow<NonNullConst<_FLDict>> for Dict<'a> {
         &self.inner
     }
 }
+
+fn get_typed(
+    dict: NonNullConst<_FLDict>,
+    key: &str,
+    ty: FLValueType,
+) -> Option<NonNullConst<_FLValue>> {
+    let val = unsafe { FLDict_Get(dict.as_ptr(), key.into()) };
+    let val = NonNullConst::new(val)?;
+    if unsafe { FLValue_GetType(val.as_ptr()) } == ty {
+        Some(val)
+    } else {
+        None
+    }
+}
+
+fn get_as_str<'a>(dict: NonNullConst<_FLDict>, key: &str) -> Option<&'a str> {
+    let val = get_typed(dict, key, FLValueType::kFLString)?;
+    let raw_s = unsafe { FLValue_AsString(val.as_ptr()) };
+    raw_s.try_into().ok()
+}
+
+fn get_as_i64(dict: NonNullConst<_FLDict>, key: &str) -> Option<i64> {
+    let val = get_typed(dict, key, FLValueType::kFLNumber)?;
+    if unsafe { FLValue_IsInteger(val.as_ptr()) } {
+        Some(unsafe { FLValue_AsInt(val.as_ptr()) })
+    } else {
+        None
+    }
+}
+
+fn get_as_u64(dict: NonNullConst<_FLDict>, key: &str) -> Option<u64> {
+    let val = get_typed(dict, key, FLValueType::kFLNumber)?;
+    if unsafe { FLValue_IsUnsigned(val.as_ptr()) } {
+        Some(unsafe { FLValue_AsUnsigned(val.as_ptr()) })
+    } else if unsafe { FLValue_IsInteger(val.as_ptr()) } {
+        unsafe { FLValue_AsInt(val.as_ptr()) }.try_into().ok()
+    } else {
+        None
+    }
+}
+
+fn get_as_bool(dict: NonNullConst<_FLDict>, key: &str) -> Option<bool> {
+    let val = get_typed(dict, key, FLValueType::kFLBoolean)?;
+    Some(unsafe { FLValue_AsBool(val.as_ptr()) })
+}
+
+fn get_as_f64(dict: NonNullConst<_FLDict>, key: &str) -> Option<f64> {
+    let val = get_typed(dict, key, FLValueType::kFLNumber)?;
+    Some(unsafe { FLValue_AsDouble(val.as_ptr()) })
+}
@@ -7,12 +7,48 @@ use std::{
     str::{ParseBoolError, Utf8Error},
 };
 
+/// New variants can be added in the future (like `WithPath`),
+/// so matching should have wildcard arm
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     Fleece(FLError),
     Custom(String),
     Unsupported(&'static str),
     InvalidFormat(Cow<'static, str>),
+    /// Error happened during deserialization of value with path
+    /// like `foo.bar[3].baz` inside root dictionary or array
+    WithPath(String, Box<Error>),
+}
+
+impl Error {
+    /// Path to value that caused the error, if known
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::WithPath(path, _) => Some(path),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn in_dict(self, key: &str) -> Self {
+        match self {
+            Error::WithPath(path, err) if path.starts_with('[') => {
+                Error::WithPath(format!("{key}{path}"), err)
+            }
+            Error::WithPath(path, err) => Error::WithPath(format!("{key}.{path}"), err),
+            err => Error::WithPath(key.to_string(), Box::new(err)),
+        }
+    }
+
+    pub(crate) fn in_array(self, idx: usize) -> Self {
+        match self {
+            Error::WithPath(path, err) if path.starts_with('[') => {
+                Error::WithPath(format!("[{idx}]{path}"), err)
+            }
+            Error::WithPath(path, err) => Error::WithPath(format!("[{idx}].{path}"), err),
+            err => Error::WithPath(format!("[{idx}]"), Box::new(err)),
+        }
+    }
 }
 
 impl From<FLError> for Error {
@@ -81,11 +117,19 @@ impl Display for Error {
             Error::Custom(msg) => write!(f, "Custom error: {}", msg),
             Error::Unsupported(msg) => write!(f, "Unsupported operation: {}", msg),
             Error::InvalidFormat(msg) => write!(f, "invalid fleece data: {}", msg),
+            Error::WithPath(path, err) => write!(f, "at {}: {}", path, err),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::WithPath(_, err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl serde::ser::Error for Error {
     fn custom<T>(msg: T) -> Self
@@ -1,13 +1,24 @@
+mod array;
 mod de;
+mod delta;
 mod dict;
 mod error;
 mod ser;
+mod shared_keys;
 
+pub use array::MutableArray;
 pub use couchbase_lite_core_sys as ffi;
-pub use de::{from_fl_dict, from_fl_value, from_slice, NonNullConst};
+pub use de::{
+    fleece_dict_to_json, fleece_to_json, from_fl_dict, from_fl_dict_with_shared_keys,
+    from_fl_value, from_slice, from_slice_with_shared_keys, validate, NonNullConst,
+};
+pub use delta::{apply_delta, create_delta};
 pub use dict::{Dict, MutableDict};
 pub use error::Error;
+#[cfg(feature = "serde_json")]
+pub use ser::from_json_value;
 pub use ser::{
-    json_to_fleece_with_encoder, to_fl_slice_result, to_fl_slice_result_with_encoder, EncodeValue,
-    FlEncoderSession,
+    json_to_fleece, json_to_fleece_with_encoder, to_fl_slice_result,
+    to_fl_slice_result_with_encoder, EncodeValue, FlEncoderSession,
 };
+pub use shared_keys::SharedKeys;
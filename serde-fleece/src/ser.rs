@@ -21,10 +21,12 @@ use crate::{
     ffi::{
         FLEncoder_BeginArray, FLEncoder_BeginDict, FLEncoder_ConvertJSON, FLEncoder_EndArray,
         FLEncoder_EndDict, FLEncoder_Finish, FLEncoder_Free, FLEncoder_GetError, FLEncoder_New,
-        FLEncoder_Reset, FLEncoder_WriteBool, FLEncoder_WriteDouble, FLEncoder_WriteFloat,
-        FLEncoder_WriteInt, FLEncoder_WriteKey, FLEncoder_WriteNull, FLEncoder_WriteString,
-        FLEncoder_WriteUInt, FLError, FLSliceResult, _FLEncoder,
+        FLEncoder_Reset, FLEncoder_WriteBool, FLEncoder_WriteData, FLEncoder_WriteDouble,
+        FLEncoder_WriteFloat, FLEncoder_WriteInt, FLEncoder_WriteKey, FLEncoder_WriteNull,
+        FLEncoder_WriteString, FLEncoder_WriteUInt, FLEncoder_WriteValue, FLError, FLSliceResult,
+        _FLEncoder, _FLValue,
     },
+    NonNullConst,
 };
 use serde::{ser, Serialize};
 use std::{borrow::Borrow, fmt::Display, ptr::NonNull};
@@ -59,6 +61,14 @@ impl Borrow<NonNull<_FLEncoder>> for FlEncoderSession {
     }
 }
 
+/// To encode several values with the same session
+impl Borrow<NonNull<_FLEncoder>> for &mut FlEncoderSession {
+    #[inline]
+    fn borrow(&self) -> &NonNull<_FLEncoder> {
+        &self.inner
+    }
+}
+
 #[inline]
 pub fn to_fl_slice_result<T>(value: &T) -> Result<FLSliceResult, Error>
 where
@@ -71,6 +81,13 @@ where
     ret
 }
 
+/// Encode `serde_json::Value` tree into fleece
+#[cfg(feature = "serde_json")]
+#[inline]
+pub fn from_json_value(value: &serde_json::Value) -> Result<FLSliceResult, Error> {
+    to_fl_slice_result(value)
+}
+
 pub fn to_fl_slice_result_with_encoder<T, FleeceEncoder>(
     value: &T,
     encoder: FleeceEncoder,
@@ -82,16 +99,51 @@ where
     let mut serializer = Serializer {
         inner: *encoder.borrow(),
     };
-    value.serialize(&mut serializer)?;
+    if let Err(err) = value.serialize(&mut serializer) {
+        // encoder can be reused for the next value, so drop partially encoded data
+        unsafe { FLEncoder_Reset(serializer.inner.as_ptr()) };
+        return Err(err);
+    }
     let mut err = FLError::kFLNoError;
     let ret = unsafe { FLEncoder_Finish(serializer.inner.as_ptr(), &mut err) };
     if !ret.is_empty() {
         Ok(ret)
     } else {
+        unsafe { FLEncoder_Reset(serializer.inner.as_ptr()) };
         Err(err.into())
     }
 }
 
+/// Encode fleece value (for example mutable collection) into byte array
+pub(crate) fn encode_fl_value(value: NonNullConst<_FLValue>) -> Result<FLSliceResult, Error> {
+    let enc =
+        unsafe { NonNull::new(FLEncoder_New()).ok_or(Error::Fleece(FLError::kFLMemoryError))? };
+    let ret = if unsafe { FLEncoder_WriteValue(enc.as_ptr(), value.as_ptr()) } {
+        let mut err = FLError::kFLNoError;
+        let data = unsafe { FLEncoder_Finish(enc.as_ptr(), &mut err) };
+        if !data.is_empty() {
+            Ok(data)
+        } else {
+            Err(err.into())
+        }
+    } else {
+        Err(Error::from(unsafe { FLEncoder_GetError(enc.as_ptr()) }))
+    };
+    unsafe { FLEncoder_Free(enc.as_ptr()) };
+    ret
+}
+
+/// Convert JSON text into fleece encoded byte array,
+/// allocates temporary encoder for conversion
+#[inline]
+pub fn json_to_fleece(json: &str) -> Result<FLSliceResult, Error> {
+    let enc =
+        unsafe { NonNull::new(FLEncoder_New()).ok_or(Error::Fleece(FLError::kFLMemoryError))? };
+    let ret = json_to_fleece_with_encoder(json.as_bytes(), enc);
+    unsafe { FLEncoder_Free(enc.as_ptr()) };
+    ret
+}
+
 /// Convert json data into fleece encoded byte array
 pub fn json_to_fleece_with_encoder<FleeceEncoder>(
     json: &[u8],
@@ -104,6 +156,7 @@ where
         inner: *encoder.borrow(),
     };
     if !unsafe { FLEncoder_ConvertJSON(serializer.inner.as_ptr(), json.into()) } {
+        unsafe { FLEncoder_Reset(serializer.inner.as_ptr()) };
         return Err(Error::Fleece(FLError::kFLEncodeError));
     }
     let mut err = FLError::kFLNoError;
@@ -111,6 +164,7 @@ where
     if !ret.is_empty() {
         Ok(ret)
     } else {
+        unsafe { FLEncoder_Reset(serializer.inner.as_ptr()) };
         Err(err.into())
     }
 }
@@ -148,6 +202,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
         encoder_write!(self, FLEncoder_WriteInt, v)
     }
+    // fleece has no 128-bit integers, so value is stored as decimal string
+    #[inline]
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        let mut buffer = itoa::Buffer::new();
+        encoder_write!(self, FLEncoder_WriteString, buffer.format(v).into())
+    }
     #[inline]
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
         encoder_write!(self, FLEncoder_WriteUInt, u64::from(v))
@@ -164,6 +224,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
         encoder_write!(self, FLEncoder_WriteUInt, v)
     }
+    // fleece has no 128-bit integers, so value is stored as decimal string
+    #[inline]
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        let mut buffer = itoa::Buffer::new();
+        encoder_write!(self, FLEncoder_WriteString, buffer.format(v).into())
+    }
     #[inline]
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
         encoder_write!(self, FLEncoder_WriteFloat, v)
@@ -183,8 +249,8 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         encoder_write!(self, FLEncoder_WriteString, v.into())
     }
     #[inline]
-    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok, Self::Error> {
-        Err(Error::Unsupported("Write raw bytes unsupported"))
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        encoder_write!(self, FLEncoder_WriteData, v.into())
     }
     #[inline]
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
@@ -284,6 +350,8 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
+        // `len` is only reserve hint for encoder, the real count of items
+        // is calculated on `FLEncoder_EndDict`, so skipped fields are fine
         encoder_write!(self, FLEncoder_BeginDict, len)?;
         Ok(self)
     }
@@ -1,4 +1,8 @@
-use crate::ffi::{FLEncoder_WriteBool, FLEncoder_WriteInt, FLEncoder_WriteString, _FLEncoder};
+use crate::ffi::{
+    FLEncoder_BeginArray, FLEncoder_EndArray, FLEncoder_WriteBool, FLEncoder_WriteDouble,
+    FLEncoder_WriteFloat, FLEncoder_WriteInt, FLEncoder_WriteNull, FLEncoder_WriteString,
+    FLEncoder_WriteUInt, _FLEncoder,
+};
 use std::ptr::NonNull;
 
 mod private {
@@ -43,6 +47,77 @@ impl EncodeValue for bool {
     }
 }
 
+impl private::Sealed for i32 {}
+impl EncodeValue for i32 {
+    #[inline]
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        unsafe { FLEncoder_WriteInt(enc.as_ptr(), i64::from(*self)) }
+    }
+}
+
+impl private::Sealed for u32 {}
+impl EncodeValue for u32 {
+    #[inline]
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        unsafe { FLEncoder_WriteUInt(enc.as_ptr(), u64::from(*self)) }
+    }
+}
+
+impl private::Sealed for u64 {}
+impl EncodeValue for u64 {
+    #[inline]
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        unsafe { FLEncoder_WriteUInt(enc.as_ptr(), *self) }
+    }
+}
+
+impl private::Sealed for f32 {}
+impl EncodeValue for f32 {
+    #[inline]
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        unsafe { FLEncoder_WriteFloat(enc.as_ptr(), *self) }
+    }
+}
+
+impl private::Sealed for f64 {}
+impl EncodeValue for f64 {
+    #[inline]
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        unsafe { FLEncoder_WriteDouble(enc.as_ptr(), *self) }
+    }
+}
+
+impl<T: EncodeValue> private::Sealed for Option<T> {}
+impl<T: EncodeValue> EncodeValue for Option<T> {
+    #[inline]
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        match self {
+            Some(x) => x.encode(enc),
+            None => unsafe { FLEncoder_WriteNull(enc.as_ptr()) },
+        }
+    }
+}
+
+impl<T: EncodeValue> private::Sealed for &[T] {}
+impl<T: EncodeValue> EncodeValue for &[T] {
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        let mut all_ok = unsafe { FLEncoder_BeginArray(enc.as_ptr(), self.len()) };
+        for x in self.iter() {
+            all_ok &= x.encode(enc);
+        }
+        all_ok &= unsafe { FLEncoder_EndArray(enc.as_ptr()) };
+        all_ok
+    }
+}
+
+impl<T: EncodeValue> private::Sealed for Vec<T> {}
+impl<T: EncodeValue> EncodeValue for Vec<T> {
+    #[inline]
+    fn encode(&self, enc: NonNull<_FLEncoder>) -> bool {
+        self.as_slice().encode(enc)
+    }
+}
+
 /// Macros to simplify creation of fleece encoded data
 #[macro_export]
 macro_rules! fleece {
@@ -51,14 +126,40 @@ macro_rules! fleece {
     //
     // Must be invoked as: fleece!(@array [$($tt)*])
     //////////////////////////////////////////////////////////////////////////
-    // Done with trailing comma.
-    (@array $enc:ident $all_ok:ident [$($elems:expr,)*]) => {
-        $($all_ok &= $crate::EncodeValue::encode(& $elems, $enc);)*
+    // Done.
+    (@array $enc:ident $all_ok:ident []) => {};
+
+    // Next element is `null`.
+    (@array $enc:ident $all_ok:ident [null $(, $($rest:tt)*)?]) => {
+        $all_ok &= $crate::ffi::FLEncoder_WriteNull($enc.as_ptr());
+        $crate::fleece!(@array $enc $all_ok [$($($rest)*)?]);
+    };
+
+    // Next element is a map.
+    (@array $enc:ident $all_ok:ident [{$($map:tt)*} $(, $($rest:tt)*)?]) => {
+        $all_ok &= $crate::ffi::FLEncoder_BeginDict($enc.as_ptr(), 0);
+        $crate::fleece!(@object $enc $all_ok () ($($map)*) ($($map)*));
+        $all_ok &= $crate::ffi::FLEncoder_EndDict($enc.as_ptr());
+        $crate::fleece!(@array $enc $all_ok [$($($rest)*)?]);
+    };
+
+    // Next element is an array.
+    (@array $enc:ident $all_ok:ident [[$($array:tt)*] $(, $($rest:tt)*)?]) => {
+        $all_ok &= $crate::ffi::FLEncoder_BeginArray($enc.as_ptr(), count_tts!($($array)*));
+        $crate::fleece!(@array $enc $all_ok [$($array)*]);
+        $all_ok &= $crate::ffi::FLEncoder_EndArray($enc.as_ptr());
+        $crate::fleece!(@array $enc $all_ok [$($($rest)*)?]);
+    };
+
+    // Next element is an expression followed by comma.
+    (@array $enc:ident $all_ok:ident [$elem:expr , $($rest:tt)*]) => {
+        $all_ok &= $crate::EncodeValue::encode(& $elem, $enc);
+        $crate::fleece!(@array $enc $all_ok [$($rest)*]);
     };
 
-    // Done without trailing comma.
-    (@array $enc:ident $all_ok:ident [$($elems:expr),*]) => {
-        $($all_ok &= $crate::EncodeValue::encode(& $elems, $enc);)*
+    // Last element is an expression with no trailing comma.
+    (@array $enc:ident $all_ok:ident [$elem:expr]) => {
+        $all_ok &= $crate::EncodeValue::encode(& $elem, $enc);
     };
 
 
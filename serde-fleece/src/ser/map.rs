@@ -51,6 +51,10 @@ where
         itoa_write_key(self.ser, v)
     }
 
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        itoa_write_key(self.ser, v)
+    }
+
     fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
         itoa_write_key(self.ser, v)
     }
@@ -67,6 +71,10 @@ where
         itoa_write_key(self.ser, v)
     }
 
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        itoa_write_key(self.ser, v)
+    }
+
     fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
         ryu_write_key(self.ser, v)
     }
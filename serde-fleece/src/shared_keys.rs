@@ -0,0 +1,41 @@
+use crate::ffi::{FLSharedKeys, FLSharedKeys_Release, FLSharedKeys_Retain, _FLSharedKeys};
+use std::ptr::NonNull;
+
+/// Table that maps dictionary keys to small integers, data encoded with
+/// shared keys can be decoded only with the same table,
+/// see `from_slice_with_shared_keys` and `from_fl_dict_with_shared_keys`.
+/// Table is retained, so it stays valid as long as `SharedKeys` exists
+pub struct SharedKeys(NonNull<_FLSharedKeys>);
+
+impl SharedKeys {
+    /// Retain shared keys, `None` if `shared_keys` is null
+    ///
+    /// # Safety
+    ///
+    /// the caller must guarantee that `shared_keys` is null or valid `FLSharedKeys`
+    #[inline]
+    pub unsafe fn retain(shared_keys: FLSharedKeys) -> Option<Self> {
+        let shared_keys = NonNull::new(shared_keys)?;
+        FLSharedKeys_Retain(shared_keys.as_ptr());
+        Some(Self(shared_keys))
+    }
+    #[inline]
+    pub fn as_ptr(&self) -> FLSharedKeys {
+        self.0.as_ptr()
+    }
+}
+
+impl Clone for SharedKeys {
+    #[inline]
+    fn clone(&self) -> Self {
+        unsafe { FLSharedKeys_Retain(self.0.as_ptr()) };
+        Self(self.0)
+    }
+}
+
+impl Drop for SharedKeys {
+    #[inline]
+    fn drop(&mut self) {
+        unsafe { FLSharedKeys_Release(self.0.as_ptr()) };
+    }
+}
@@ -1,6 +1,7 @@
 use ffi::{
-    FLEncoder_Free, FLEncoder_New, FLSliceResult, FLTrust, FLValue_FromData, FLValue_ToJSON,
-    _FLEncoder,
+    FLArray_Count, FLEncoder_Free, FLEncoder_New, FLEncoder_SetSharedKeys, FLSharedKeys_New,
+    FLSharedKeys_Release, FLSliceResult, FLTrust, FLValueType, FLValue_AsDict, FLValue_FromData,
+    FLValue_GetType, FLValue_ToJSON, _FLEncoder,
 };
 use rustc_hash::FxHashMap;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -18,6 +19,10 @@ fn test_ser_primitive() {
     assert_eq!("9223372036854775807", to_fleece_to_json(&i64::MAX));
     assert_eq!("18446744073709551615", to_fleece_to_json(&u64::MAX));
     assert_eq!("0", to_fleece_to_json(&0_i64));
+    assert_eq!(
+        "\"340282366920938463463374607431768211455\"",
+        to_fleece_to_json(&u128::MAX)
+    );
     assert_eq!(
         "\"This is text, привет\"",
         to_fleece_to_json(&"This is text, привет")
@@ -178,7 +183,7 @@ macro_rules! test_primive_ser_deser {
 fn test_de_primitive() {
     assert_eq!(true, ser_deser(&true).unwrap());
     assert_eq!(false, ser_deser(&false).unwrap());
-    test_primive_ser_deser!(i8 i16 i32 i64 u8 u16 u32 u64);
+    test_primive_ser_deser!(i8 i16 i32 i64 i128 u8 u16 u32 u64 u128);
     assert_eq!(-1_i32, ser_deser(&-1_i32).unwrap());
     assert_eq!(-1e10f32, ser_deser(&-1e10f32).unwrap());
     assert_eq!(f32::MAX, ser_deser(&f32::MAX).unwrap());
@@ -189,6 +194,14 @@ fn test_de_primitive() {
     assert_eq!("Ну что?", ser_deser(&"Ну что?".to_string()).unwrap());
     let expect = 'ю';
     assert_eq!(expect, ser_deser(&expect).unwrap());
+
+    let data = to_fl_slice_result(&17_u64).unwrap();
+    assert_eq!(17_u128, from_slice::<u128>(data.as_bytes()).unwrap());
+    let data = to_fl_slice_result(&-17_i64).unwrap();
+    assert!(from_slice::<u128>(data.as_bytes()).is_err());
+    let mut ids = FxHashMap::default();
+    ids.insert(u128::MAX, i128::MIN);
+    assert_eq!(ids, ser_deser(&ids).unwrap());
 }
 
 #[test]
@@ -387,6 +400,40 @@ fn test_de_struct_errors_corner_cases() {
         },
         from_slice::<S4>(fleece!({"s": "uuu", "i": 34}).unwrap().as_bytes()).unwrap()
     );
+    let data = to_fl_slice_result(&(35, "zzz")).unwrap();
+    assert_eq!(
+        S4 {
+            i: 35,
+            s: "zzz".into(),
+        },
+        from_slice::<S4>(data.as_bytes()).unwrap()
+    );
+
+    #[derive(Deserialize, Debug)]
+    struct Baz {
+        #[allow(dead_code)]
+        baz: i32,
+    }
+    #[derive(Deserialize, Debug)]
+    struct Foo {
+        #[allow(dead_code)]
+        bar: Vec<Baz>,
+    }
+    #[derive(Deserialize, Debug)]
+    struct Root {
+        #[allow(dead_code)]
+        foo: Foo,
+    }
+    let data = json_to_fleece(r#"{"foo": {"bar": [{"baz": 1}, {"baz": "2"}]}}"#).unwrap();
+    let err = from_slice::<Root>(data.as_bytes()).unwrap_err();
+    assert_eq!(Some("foo.bar[1].baz"), err.path());
+    assert_eq!(
+        "at foo.bar[1].baz: invalid fleece data: Wrong data type: expect kFLNumber and integer, got kFLString",
+        err.to_string()
+    );
+    let data = to_fl_slice_result(&[vec![1], vec![2, -3]]).unwrap();
+    let err = from_slice::<Vec<Vec<u32>>>(data.as_bytes()).unwrap_err();
+    assert_eq!(Some("[1][1]"), err.path());
 }
 
 #[test]
@@ -412,6 +459,50 @@ fn test_de_enum() {
     assert_eq!(expected, ser_deser(&expected).unwrap());
 }
 
+#[test]
+fn test_de_untagged_enum() {
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum E {
+        Nothing,
+        Flag(bool),
+        Int(i64),
+        Float(f64),
+        Text(String),
+        Point(Point),
+        List(Vec<E>),
+    }
+
+    let expected = E::List(vec![
+        E::Nothing,
+        E::Flag(true),
+        E::Int(-5),
+        E::Int(42),
+        E::Float(0.5),
+        E::Text("строка".into()),
+        E::Point(Point { x: 1, y: -1 }),
+        E::List(vec![]),
+    ]);
+    assert_eq!(expected, ser_deser(&expected).unwrap());
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    #[serde(untagged)]
+    enum Blob<'a> {
+        Text(String),
+        Data(&'a [u8]),
+    }
+    let data = to_fl_slice_result(&Bytes(b"\x00\x01")).unwrap();
+    assert_eq!(
+        Blob::Data(&[0, 1]),
+        from_slice::<Blob>(data.as_bytes()).unwrap()
+    );
+}
+
 #[test]
 fn test_de_collections() {
     let expect = [1_u8, 2_u8];
@@ -466,6 +557,9 @@ fn test_de_fleece_dict() {
     let mut md = MutableDict::new().unwrap();
     md.set_i64("a", 5);
     md.set_string("b", "16");
+    let dict = md.as_dict();
+    assert_eq!(dict.as_ptr(), dict.as_non_null().as_ptr() as *const _);
+    assert_eq!(format!("{:p}", dict.as_ptr()), format!("{dict:?}"));
     let data: JsonData = from_fl_dict(md.as_dict()).unwrap();
     assert_eq!(
         JsonData {
@@ -476,6 +570,132 @@ fn test_de_fleece_dict() {
     );
 }
 
+#[test]
+fn test_mutable_array() {
+    let mut arr = MutableArray::new().unwrap();
+    arr.append_i64(5);
+    arr.append_string("16");
+    arr.append_bool(true);
+    let mut md = MutableDict::new().unwrap();
+    md.set_i64("a", 17);
+    arr.append_dict(&md);
+    let mut nested = MutableArray::new().unwrap();
+    nested.append_string("b");
+    arr.append_array(&nested);
+    assert_eq!(
+        r#"[5,"16",true,{"a":17},["b"]]"#,
+        fleece_encoded_to_json(arr.to_fl_slice_result().unwrap())
+    );
+}
+
+#[test]
+fn test_mutable_dict_nested() {
+    let mut md = MutableDict::new().unwrap();
+    md.set_i64("a", 5);
+    md.set_string("b", "16");
+    assert_eq!(Some(5), md.get_as_i64("a"));
+    assert_eq!(None, md.get_as_i64("b"));
+    assert_eq!(Some("16"), md.get_as_str("b"));
+    assert_eq!(None, md.get_as_bool("a"));
+    assert_eq!(Some(5.), md.get_as_f64("a"));
+
+    let mut nested = MutableDict::new().unwrap();
+    nested.set_string("c", "d");
+    md.set_dict("n", &nested);
+    let mut arr = MutableArray::new().unwrap();
+    arr.append_bool(false);
+    md.set_array("arr", &arr);
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Nested {
+        c: String,
+    }
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Data {
+        a: i32,
+        b: String,
+        n: Nested,
+        arr: Vec<bool>,
+    }
+    let data: Data = from_fl_dict(md.as_dict()).unwrap();
+    assert_eq!(
+        Data {
+            a: 5,
+            b: "16".into(),
+            n: Nested { c: "d".into() },
+            arr: vec![false],
+        },
+        data
+    );
+
+    let data = json_to_fleece(
+        r#"{"i":-1,"u":18446744073709551615,"f":0.5,"b":true,"n":{"s":"x"},"arr":[1]}"#,
+    )
+    .unwrap();
+    let root = unsafe { FLValue_AsDict(FLValue_FromData(data.as_fl_slice(), FLTrust::kFLTrusted)) };
+    let dict = Dict::new(&root).unwrap();
+    assert_eq!(Some(-1), dict.get_as_i64("i"));
+    assert_eq!(None, dict.get_as_u64("i"));
+    assert_eq!(Some(u64::MAX), dict.get_as_u64("u"));
+    assert_eq!(Some(0.5), dict.get_as_f64("f"));
+    assert_eq!(None, dict.get_as_i64("f"));
+    assert_eq!(Some(true), dict.get_as_bool("b"));
+    assert_eq!(None, dict.get_as_bool("missing"));
+    assert_eq!(Some("x"), dict.get_dict("n").unwrap().get_as_str("s"));
+    let s = dict.get_path("n.s").unwrap();
+    assert_eq!(FLValueType::kFLString, unsafe {
+        FLValue_GetType(s.as_ptr())
+    });
+    assert!(dict.get_path("n.missing").is_none());
+    assert!(dict.get_path("b.s").is_none());
+    assert!(dict.get_path("i").is_some());
+    assert!(dict.get_dict("arr").is_none());
+    let arr = dict.get_array("arr").unwrap();
+    assert_eq!(1, unsafe { FLArray_Count(arr.as_ptr()) });
+}
+
+#[test]
+fn test_mutable_dict_from_serialize() {
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Data {
+        a: i32,
+        b: String,
+    }
+    let mut md = MutableDict::from_serialize(&Data {
+        a: 5,
+        b: "16".into(),
+    })
+    .unwrap();
+    md.set_i64("a", 6);
+    let fl_dict = md.as_dict().as_ptr();
+    assert_eq!(
+        r#"{"a":6,"b":"16"}"#,
+        Dict::new(&fl_dict).unwrap().to_json()
+    );
+    let data: Data = from_fl_dict(md.as_dict()).unwrap();
+    assert_eq!(
+        Data {
+            a: 6,
+            b: "16".into()
+        },
+        data
+    );
+    assert!(MutableDict::from_serialize(&5).is_err());
+}
+
+#[test]
+fn test_json_delta() {
+    let old = fleece!({ "a": 1, "b": "boo" }).unwrap();
+    let new = fleece!({ "a": 2, "b": "boo" }).unwrap();
+    let delta = create_delta(old.as_bytes(), new.as_bytes()).unwrap();
+    assert!(!delta.is_empty());
+    let restored = apply_delta(old.as_bytes(), delta.as_bytes()).unwrap();
+    assert_eq!(r#"{"a":2,"b":"boo"}"#, fleece_encoded_to_json(restored));
+    assert!(create_delta(old.as_bytes(), old.as_bytes())
+        .unwrap()
+        .is_empty());
+}
+
 #[test]
 fn test_fleece_macro() {
     assert_eq!(
@@ -523,6 +743,172 @@ fn test_fleece_macro() {
         r#"{"channels":["a","b"],"f2":"boo"}"#,
         fleece_encoded_to_json(fleece!({ "channels": ["a", "b"], "f2" : "boo" }).unwrap())
     );
+
+    let count: u64 = 42;
+    let missing: Option<i64> = None;
+    assert_eq!(
+        r#"{"count":42,"missing":null,"ratio":0.5,"some":-1}"#,
+        fleece_encoded_to_json(
+            fleece!({ "ratio": 0.5, "count": count, "missing": missing, "some": Some(-1) })
+                .unwrap()
+        )
+    );
+
+    let a = 2;
+    assert_eq!(
+        r#"{"items":[{"a":1},{"a":2,"b":[true,null]},[[],["x"]]],"n":1}"#,
+        fleece_encoded_to_json(
+            fleece!({ "items": [{"a": 1}, {"a": a, "b": [true, null]}, [[], ["x"]]], "n": 1 })
+                .unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_json_fleece_round_trip() {
+    let json = r#"{"a":[1,2.5,"три"],"b":{"c":null,"d":true}}"#;
+    let data = json_to_fleece(json).unwrap();
+    assert_eq!(json, fleece_to_json(data.as_bytes()).unwrap());
+    let fl_dict =
+        unsafe { FLValue_AsDict(FLValue_FromData(data.as_fl_slice(), FLTrust::kFLUntrusted)) };
+    assert_eq!(
+        json,
+        fleece_dict_to_json(Dict::new(&fl_dict).unwrap()).unwrap()
+    );
+
+    assert!(json_to_fleece("{not json").is_err());
+    assert!(fleece_to_json(b"garbage").is_err());
+    assert!(validate(data.as_bytes()).is_ok());
+    assert!(validate(b"garbage").is_err());
+}
+
+#[test]
+fn test_de_json_value() {
+    let json = r#"{"a":[1,-2,2.5,"три"],"b":{"c":null,"d":true},"e":18446744073709551615}"#;
+    let data = json_to_fleece(json).unwrap();
+    let value: serde_json::Value = from_slice(data.as_bytes()).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(json).unwrap(),
+        value
+    );
+
+    let data = to_fl_slice_result(&Bytes(b"abc")).unwrap();
+    assert_eq!(b"abc", from_slice::<&[u8]>(data.as_bytes()).unwrap());
+    assert_eq!(
+        b"abc".to_vec(),
+        from_slice::<Vec<u8>>(data.as_bytes()).unwrap()
+    );
+
+    #[cfg(feature = "serde_json")]
+    {
+        let data = serde_fleece::from_json_value(&value).unwrap();
+        assert_eq!(
+            value,
+            from_slice::<serde_json::Value>(data.as_bytes()).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_skip_serializing_if() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct S {
+        i: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        s: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        v: Vec<i32>,
+    }
+    let s = S {
+        i: 17,
+        s: None,
+        v: vec![],
+    };
+    assert_eq!(r#"{"i":17}"#, to_fleece_to_json(&s));
+    assert_eq!(s, ser_deser(&s).unwrap());
+    let s = S {
+        i: 17,
+        s: Some("aaa".into()),
+        v: vec![],
+    };
+    assert_eq!(r#"{"i":17,"s":"aaa"}"#, to_fleece_to_json(&s));
+    assert_eq!(s, ser_deser(&s).unwrap());
+
+    // declared count is only a hint for fleece encoder
+    struct OverCount;
+    impl Serialize for OverCount {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            use serde::ser::SerializeStruct;
+            let mut st = serializer.serialize_struct("OverCount", 3)?;
+            st.serialize_field("i", &5)?;
+            st.skip_field("s")?;
+            st.skip_field("v")?;
+            st.end()
+        }
+    }
+    assert_eq!(r#"{"i":5}"#, to_fleece_to_json(&OverCount));
+    assert_eq!(
+        S {
+            i: 5,
+            s: None,
+            v: vec![],
+        },
+        from_slice::<S>(to_fl_slice_result(&OverCount).unwrap().as_bytes()).unwrap()
+    );
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl Serialize for Bytes<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+#[test]
+fn test_de_with_shared_keys() {
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Item {
+        name: String,
+    }
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Doc {
+        id: u32,
+        items: Vec<Item>,
+    }
+    let doc = Doc {
+        id: 17,
+        items: vec![
+            Item {
+                name: "first".into(),
+            },
+            Item {
+                name: "second".into(),
+            },
+        ],
+    };
+    let shared_keys = unsafe {
+        let sk = FLSharedKeys_New();
+        let shared_keys = SharedKeys::retain(sk).unwrap();
+        FLSharedKeys_Release(sk);
+        shared_keys
+    };
+    let mut enc = Encoder::new();
+    unsafe { FLEncoder_SetSharedKeys(enc.inner.as_ptr(), shared_keys.as_ptr()) };
+    let data = to_fl_slice_result_with_encoder(&doc, enc.session()).unwrap();
+
+    // keys are encoded as integers, so they can not be decoded without shared keys
+    assert!(from_slice::<Doc>(data.as_bytes()).is_err());
+    assert_eq!(
+        doc,
+        from_slice_with_shared_keys::<Doc>(data.as_bytes(), &shared_keys).unwrap()
+    );
+    let root = unsafe { FLValue_FromData(data.as_fl_slice(), FLTrust::kFLUntrusted) };
+    let dict = NonNullConst::new(unsafe { FLValue_AsDict(root) }).unwrap();
+    assert_eq!(
+        doc,
+        from_fl_dict_with_shared_keys::<Doc, _>(dict, &shared_keys).unwrap()
+    );
 }
 
 fn to_fleece_to_json<T: Serialize>(value: &T) -> String {